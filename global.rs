@@ -1,3 +1,4 @@
+use crate::judger::provider::VerdictRules;
 use crate::judger::RemoteJudgeConfig;
 use crate::server::ServerConfig;
 use once_cell::sync::OnceCell;
@@ -5,8 +6,7 @@ use std::collections::HashMap;
 
 static REMOTE_JUDGE_CONFIG_MAP: OnceCell<HashMap<String, RemoteJudgeConfig>> = OnceCell::new();
 static SERVER_CONFIG: OnceCell<ServerConfig> = OnceCell::new();
-static JUDGE_STATUS_MAP: OnceCell<HashMap<&'static str, HashMap<&'static str, &'static str>>> =
-    OnceCell::new();
+static JUDGE_STATUS_MAP: OnceCell<HashMap<&'static str, VerdictRules>> = OnceCell::new();
 
 use simple_log::LogConfigBuilder;
 use std::path::PathBuf;
@@ -75,91 +75,96 @@ pub mod task_constant {
     pub mod names {
         pub const JUDGE: &str = "judge";
         pub const GET_PROBLEM: &str = "get_problem";
+        pub const TRACK: &str = "track";
+        pub const SUBSCRIBE: &str = "subscribe";
     }
 }
 
-pub mod judge_status {
-    pub const AC: &str = "Accepted";
-    pub const WA: &str = "Wrong Answer";
-    pub const RE: &str = "Runtime Error";
-    pub const TLE: &str = "Time Limit Exceeded";
-    pub const MLE: &str = "Memory Limit Exceeded";
-    pub const OLE: &str = "Output Limit Exceeded";
-    pub const CE: &str = "Compile Error";
-    pub const RN: &str = "Running";
-    pub const WT: &str = "Waiting";
-}
-
-pub fn judge_status_map(remote_judger_name: &str) -> Option<&HashMap<&'static str, &'static str>> {
-    JUDGE_STATUS_MAP.get().unwrap().get(remote_judger_name)
+// 远程 oj 的测评结果映射规则, gym 与 codeforces 共用默认规则
+// 不支持的远程 oj 返回 None, 由调用方转换成错误
+pub fn judge_status_map(remote_judge_name: &str) -> Option<&'static VerdictRules> {
+    JUDGE_STATUS_MAP.get()?.get(remote_judge_name)
 }
 
 pub mod judge_status_map {
-    use super::judge_status;
-    use once_cell::sync::OnceCell;
-    use std::collections::HashMap;
-    pub static CODEFORCES_STATUS_MAP: OnceCell<HashMap<&'static str, &'static str>> =
-        OnceCell::new();
-    pub static HDU_STATUS_MAP: OnceCell<HashMap<&'static str, &'static str>> = OnceCell::new();
-    pub static ATCODER_STATUS_MAP: OnceCell<HashMap<&'static str, &'static str>> = OnceCell::new();
-    pub fn judge_status_map_init() {
-        codeforces_status_map_init();
-        hdu_status_map_init();
-        atcoder_status_map_init();
-    }
+    use super::remote_judge_constant::names;
+    use super::{remote_judge_config, JUDGE_STATUS_MAP};
+    use crate::judger::provider::{Verdict, VerdictRule, VerdictRules};
 
-    fn codeforces_status_map_init() {
-        let cf_map = vec![
-            ("Accepted", judge_status::AC),
-            ("Happy", judge_status::AC),
-            ("Wrong answer", judge_status::WA),
-            ("Runtime error", judge_status::RE),
-            ("Time limit exceeded", judge_status::TLE),
-            ("Memory limit exceeded", judge_status::MLE),
-            ("Compilation error", judge_status::CE),
-            ("Running", judge_status::RN),
-            ("queue", judge_status::WT),
-            ("Pending", judge_status::WT),
+    pub fn judge_status_map_init() {
+        let map = [
+            (names::CODEFORCES, codeforces_rules as fn() -> Vec<VerdictRule>),
+            (names::GYM, codeforces_rules),
+            (names::HDU, hdu_rules),
+            (names::ATCODER, atcoder_rules),
         ]
         .into_iter()
+        .map(|(name, defaults)| {
+            let configured = remote_judge_config(name)
+                .map(|c| c.verdict_rules.as_slice())
+                .unwrap_or_default();
+            (name, VerdictRules::new(configured, defaults()))
+        })
         .collect();
-        CODEFORCES_STATUS_MAP.set(cf_map).unwrap();
+        JUDGE_STATUS_MAP.set(map).unwrap();
     }
 
-    fn hdu_status_map_init() {
-        let hdu_map = vec![
-            ("Accepted", judge_status::AC),
-            ("Wrong Answer", judge_status::WA),
-            ("Runtime Error", judge_status::RE),
-            ("Time Limit Exceeded", judge_status::TLE),
-            ("Memory Limit Exceeded", judge_status::MLE),
-            ("Presentation Error", judge_status::WA),
-            ("Output Limit Exceeded", judge_status::OLE),
-            ("Compilation Error", judge_status::CE),
-            ("Running", judge_status::RN),
-            ("Queuing", judge_status::WT),
-            ("Pending", judge_status::WT),
-            ("Compiling", judge_status::WT),
+    // codeforces 的结果带有测试点信息, 例如 "Wrong answer on test 3", "Running on test 5"
+    fn codeforces_rules() -> Vec<VerdictRule> {
+        vec![
+            VerdictRule::contains("Running", Verdict::Running),
+            VerdictRule::contains("queue", Verdict::Waiting),
+            VerdictRule::contains("Pending", Verdict::Waiting),
+            VerdictRule::contains("Compilation error", Verdict::CompileError),
+            VerdictRule::contains("Wrong answer", Verdict::WrongAnswer),
+            VerdictRule::contains("Runtime error", Verdict::RuntimeError),
+            VerdictRule::contains("Time limit exceeded", Verdict::TimeLimitExceeded),
+            VerdictRule::contains("Memory limit exceeded", Verdict::MemoryLimitExceeded),
+            VerdictRule::contains("Idleness limit exceeded", Verdict::IdlenessLimitExceeded),
+            VerdictRule::contains("Denial of judgement", Verdict::SystemError),
+            VerdictRule::contains("Skipped", Verdict::Skipped),
+            VerdictRule::contains("Hacked", Verdict::Hacked),
+            VerdictRule::contains("Partial", Verdict::Partial),
+            // 通过预测试不是最终结果, 不能按 Accepted 计分
+            VerdictRule::contains("Pretests passed", Verdict::Partial),
+            VerdictRule::contains("Accepted", Verdict::Accepted),
+            VerdictRule::contains("Happy", Verdict::Accepted),
         ]
-        .into_iter()
-        .collect();
-        HDU_STATUS_MAP.set(hdu_map).unwrap();
     }
 
-    fn atcoder_status_map_init() {
-        let atocder_map = vec![
-            ("AC", judge_status::AC),
-            ("WA", judge_status::WA),
-            ("TLE", judge_status::TLE),
-            ("MLE", judge_status::MLE),
-            ("RE", judge_status::RE),
-            ("CE", judge_status::CE),
-            ("OLE", judge_status::OLE),
-            ("WJ", judge_status::WT),
-            ("Judging", judge_status::RN),
+    fn hdu_rules() -> Vec<VerdictRule> {
+        vec![
+            VerdictRule::contains("Running", Verdict::Running),
+            VerdictRule::contains("Queuing", Verdict::Waiting),
+            VerdictRule::contains("Pending", Verdict::Waiting),
+            VerdictRule::contains("Compiling", Verdict::Waiting),
+            VerdictRule::prefix("Accepted", Verdict::Accepted),
+            VerdictRule::prefix("Wrong Answer", Verdict::WrongAnswer),
+            VerdictRule::prefix("Presentation Error", Verdict::PresentationError),
+            VerdictRule::prefix("Runtime Error", Verdict::RuntimeError),
+            VerdictRule::prefix("Time Limit Exceeded", Verdict::TimeLimitExceeded),
+            VerdictRule::prefix("Memory Limit Exceeded", Verdict::MemoryLimitExceeded),
+            VerdictRule::prefix("Output Limit Exceeded", Verdict::OutputLimitExceeded),
+            VerdictRule::prefix("Compilation Error", Verdict::CompileError),
+            VerdictRule::prefix("System Error", Verdict::SystemError),
+        ]
+    }
+
+    fn atcoder_rules() -> Vec<VerdictRule> {
+        vec![
+            VerdictRule::exact("AC", Verdict::Accepted),
+            VerdictRule::exact("WA", Verdict::WrongAnswer),
+            VerdictRule::exact("TLE", Verdict::TimeLimitExceeded),
+            VerdictRule::exact("MLE", Verdict::MemoryLimitExceeded),
+            VerdictRule::exact("RE", Verdict::RuntimeError),
+            VerdictRule::exact("CE", Verdict::CompileError),
+            VerdictRule::exact("OLE", Verdict::OutputLimitExceeded),
+            VerdictRule::exact("IE", Verdict::SystemError),
+            VerdictRule::exact("WJ", Verdict::Waiting),
+            VerdictRule::exact("WR", Verdict::Waiting),
+            VerdictRule::exact("Judging", Verdict::Running),
+            // 测评中显示为进度, 例如 "3/20" 或 "3/20 WA"
+            VerdictRule::regex(r"^\d+\s*/\s*\d+", Verdict::Running),
         ]
-        .into_iter()
-        .collect();
-        ATCODER_STATUS_MAP.set(atocder_map).unwrap();
     }
 }
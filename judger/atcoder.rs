@@ -1,66 +1,49 @@
-use super::Handler;
-use crate::global::{self, judge_status, remote_judge_config, remote_judge_constant as constant};
+use super::pool::{account_pool, AccountLease};
+use crate::global::{self, remote_judge_constant as constant};
 use crate::judger::utils::get_text_of_element;
 
-use super::provider::{Problem, Provider, SubmissionStatus};
-use super::utils::{extract_integer, get_text_arr_of_children_element};
+use super::attribution::{Candidate, Expectation};
+use super::provider::{
+    CaseDetail, CaseFile, CaseResult, CompileResult, JudgeDetail, Problem, Provider,
+    SubmissionHandle, SubmissionStatus, Subtask, Verdict, VerdictRules,
+};
+use super::utils::{extract_integer, get_text_arr_of_children_element, parse_datetime};
+use super::watermark::watermark_source;
 
 use anyhow::{anyhow, Ok};
-use once_cell::sync::OnceCell;
 use scraper::{Html, Selector};
-use tokio::sync::Mutex;
+use std::collections::HashMap;
 
-async fn get_atcoder_handler() -> anyhow::Result<&'static Handler> {
-    static HANDLERS: OnceCell<Vec<Handler>> = OnceCell::new();
-    let handlers = HANDLERS.get_or_init(|| {
-        let Some(config) = remote_judge_config(constant::names::ATCODER) else {
-            return vec![];
-        };
-        config
-            .accounts
-            .iter()
-            .map(|account| {
-                Handler::new(
-                    account.handler.clone(),
-                    account.password.clone(),
-                    constant::base_url::ATCODER,
-                )
-            })
-            .collect()
-    });
-    if handlers.is_empty() {
-        return Err(anyhow!("未注册 atcoder 账户"));
-    }
-
-    static IDX: OnceCell<Mutex<usize>> = OnceCell::new();
-    let idx = IDX.get_or_init(|| Mutex::new(0));
-
-    let mut c = idx.lock().await;
-    let now = *c;
-    *c += 1;
-    if *c >= handlers.len() {
-        *c = 0
-    }
-    Ok(&handlers[now])
+fn status_rules() -> anyhow::Result<&'static VerdictRules> {
+    global::judge_status_map(constant::names::ATCODER)
+        .ok_or(anyhow!("未配置 {} 的测评结果映射", constant::names::ATCODER))
 }
 
-pub fn status_map(status: &str) -> String {
-    return global::judge_status_map::ATCODER_STATUS_MAP
-        .get()
-        .unwrap()
-        .get(status)
-        .unwrap_or(&judge_status::RN.into())
-        .to_string();
+// 提交时的语言 id 对应提交列表中显示的语言
+fn lang_label(lang_id: &str) -> Option<String> {
+    let label = match lang_id {
+        "4003" => "C++ (GCC 9.2.1)",
+        "4005" => "Java (OpenJDK 11.0.6)",
+        "4006" => "Python (3.8.2)",
+        _ => return None,
+    };
+    Some(label.into())
 }
 
 pub struct Atcoder {
-    h: &'static Handler,
+    h: AccountLease,
 }
 
 impl Atcoder {
     pub async fn new() -> anyhow::Result<Self> {
         Ok(Self {
-            h: get_atcoder_handler().await?,
+            h: account_pool(constant::names::ATCODER)?.acquire()?,
+        })
+    }
+
+    pub fn from_handle(handle: &SubmissionHandle) -> anyhow::Result<Self> {
+        Ok(Self {
+            h: account_pool(&handle.oj)?.lease(&handle.account)?,
         })
     }
 
@@ -88,11 +71,24 @@ impl Atcoder {
         )
     }
 
+    // 登录结果反馈给账户池, 连续失败的账户会被隔离; 登录成功后持久化会话
     pub async fn ensure_login(&self) -> anyhow::Result<String> {
+        let res = self.login().await;
+        self.h.report_login(&res);
+        if res.is_ok() {
+            self.h.save_session().await;
+        }
+        res
+    }
+
+    async fn login(&self) -> anyhow::Result<String> {
         let resp = self.h.req.get("").await?;
         let logged = Atcoder::is_login(&resp);
         let text = resp.text().await?;
-        let csrf_token = Atcoder::get_csrf_token(&text).unwrap_or_default();
+        // 正常页面都带有 csrfToken, 没有说明访问被拦截或正在维护, 熔断一段时间再试
+        let Some(csrf_token) = Atcoder::get_csrf_token(&text) else {
+            return Err(self.h.req.block("atcoder 页面未包含 csrfToken, 可能正在维护或访问已经被拦截"));
+        };
         if logged {
             return Ok(csrf_token);
         }
@@ -106,17 +102,75 @@ impl Atcoder {
         if Atcoder::is_login(&resp2) {
             return Ok(csrf_token);
         }
+        self.h.check_banned(&resp2.text().await?)?;
         Err(anyhow!("无法登录"))
     }
 
-    fn extract_submission_id_from_html(html: &str) -> anyhow::Result<String> {
+    fn extract_candidates_from_html(html: &str) -> Vec<Candidate> {
         let document = Html::parse_document(html);
-        let selector = Selector::parse(r#"tr td[data-id]"#).unwrap();
+        let selector = Selector::parse(r#"table tbody tr"#).unwrap();
+        let id_s = Selector::parse(r#"td[data-id]"#).unwrap();
+        let task_s = Selector::parse(r#"a[href*="/tasks/"]"#).unwrap();
+        let time_s = Selector::parse("time").unwrap();
+        let td_s = Selector::parse("td").unwrap();
+        document
+            .select(&selector)
+            .filter_map(|tr| {
+                let id = tr.select(&id_s).next()?.value().attr("data-id")?;
+                Some(Candidate {
+                    submission_id: id.into(),
+                    problem: tr
+                        .select(&task_s)
+                        .next()
+                        .and_then(|a| a.value().attr("href"))
+                        .and_then(|href| href.rsplit('/').next())
+                        .unwrap_or_default()
+                        .into(),
+                    lang: tr.select(&td_s).nth(3).map(get_text_of_element),
+                    submit_time: tr
+                        .select(&time_s)
+                        .next()
+                        .and_then(|t| parse_datetime(&get_text_of_element(t), 9 * 3600)),
+                })
+            })
+            .collect()
+    }
 
-        if let Some(x) = document.select(&selector).next() {
-            return Ok(x.value().attr("data-id").unwrap_or_default().into());
+    // submissions/me/status/json 返回的各提交状态, Html 为结果所在的表格单元格
+    fn extract_statuses_from_json(text: &str) -> HashMap<String, String> {
+        let value: serde_json::Value = serde_json::from_str(text).unwrap_or_default();
+        let span_s = Selector::parse("span").unwrap();
+        value["Result"]
+            .as_object()
+            .map(|result| {
+                result
+                    .iter()
+                    .filter_map(|(sid, v)| {
+                        let html = Html::parse_fragment(&format!(
+                            "<table><tr>{}</tr></table>",
+                            v["Html"].as_str()?
+                        ));
+                        let span = html.select(&span_s).next()?;
+                        Some((sid.clone(), get_text_of_element(span)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    async fn fetch_source(&self, contest_id: &str, submission_id: String) -> anyhow::Result<String> {
+        let resp = self
+            .h
+            .req
+            .get(&format!("contests/{}/submissions/{}", contest_id, submission_id))
+            .await?;
+        let html = resp.text().await?;
+        let document = Html::parse_document(&html);
+        let selector = Selector::parse(r#"pre[id="submission-code"]"#).unwrap();
+        match document.select(&selector).next() {
+            Some(x) => Ok(x.text().collect()),
+            None => Err(anyhow!("获取源码失败")),
         }
-        Err(anyhow!("提交失败"))
     }
 }
 
@@ -158,10 +212,12 @@ impl Provider for Atcoder {
         problem_id: &str,
         source: &str,
         lang_id: &str,
-    ) -> anyhow::Result<(String, serde_json::Value)> {
+    ) -> anyhow::Result<SubmissionHandle> {
         let csrf_token = self.ensure_login().await?;
         let pos = problem_id.find('_').unwrap_or(0);
         let contest_id = &problem_id[..pos];
+        let (source, watermark) = watermark_source(constant::names::ATCODER, lang_id, source);
+        let marker = watermark.map(|w| w.value);
         // let problem_idx = &problem_id[pos + 1..];
 
         // https://atcoder.jp/contests/abc064/submit
@@ -173,9 +229,7 @@ impl Provider for Atcoder {
             "csrf_token":csrf_token
         });
 
-        self.h.accquire(problem_id).await;
-
-        let future = || async {
+        let future = |expectation: Expectation| async move {
             let resp = self
                 .h
                 .req
@@ -183,24 +237,50 @@ impl Provider for Atcoder {
                 .await?;
 
             if !resp.url().as_str().ends_with("me") {
+                self.h.check_banned(&resp.text().await?)?;
                 return Err(anyhow!("提交代码失败"));
             }
             let html = resp.text().await?;
-            let sid = Atcoder::extract_submission_id_from_html(&html)?;
-            Ok(format!("{}_{}", contest_id, sid))
+            let candidates = Atcoder::extract_candidates_from_html(&html);
+            let sid = expectation
+                .attribute(candidates, |sid| self.fetch_source(contest_id, sid))
+                .await?;
+            Ok(sid)
+        };
+        let res = {
+            // submissions/me 列出的是整场比赛的提交, 按比赛加锁
+            let _permit = self.h.accquire(contest_id).await;
+            let html = self
+                .h
+                .req
+                .get(&format!("contests/{}/submissions/me", contest_id))
+                .await?
+                .text()
+                .await?;
+            let known_ids = Atcoder::extract_candidates_from_html(&html)
+                .into_iter()
+                .map(|c| c.submission_id)
+                .collect();
+            let expectation = Expectation::new(problem_id, lang_label(lang_id), known_ids)
+                .with_marker(marker.clone());
+            future(expectation).await?
         };
-        self.h.release(problem_id).await;
-        let res = future().await?;
-        let pos = res.find('_').unwrap();
-        let value =
-            serde_json::json!({"submissionId": &res[pos + 1..],  "account": self.h.username});
-        Ok((res, value))
+        Ok(SubmissionHandle {
+            oj: constant::names::ATCODER.into(),
+            account: self.h.username.clone(),
+            contest_id: Some(contest_id.into()),
+            problem_id: problem_id.into(),
+            remote_id: res,
+            marker,
+        })
     }
 
-    async fn poll(&self, submission_id: &str) -> anyhow::Result<SubmissionStatus> {
-        let pos = submission_id.find('_').unwrap_or(0);
-        let contest_id = &submission_id[..pos];
-        let sid = &submission_id[pos + 1..];
+    async fn poll(&self, handle: &SubmissionHandle) -> anyhow::Result<SubmissionStatus> {
+        if handle.account != self.h.username {
+            return Err(anyhow!("提交记录不属于当前账户"));
+        }
+        let contest_id = handle.contest_id.as_deref().unwrap_or_default();
+        let sid = handle.remote_id.as_str();
 
         let resp = self
             .h
@@ -213,20 +293,23 @@ impl Provider for Atcoder {
         let selector = Selector::parse(r#"td[id="judge-status"] span"#).unwrap();
 
         if let Some(ele) = document.select(&selector).next() {
+            let rules = status_rules()?;
             let mut s = SubmissionStatus::default();
             s.info = get_text_of_element(ele);
-            s.status = status_map(&s.info);
+            s.status = rules.resolve(&s.info);
             s.submission_id = sid.to_string();
 
-            if s.status != judge_status::WT && s.status != judge_status::RN {
+            if !s.status.is_pending() {
                 s.is_over = true;
-                if s.status == judge_status::CE {
+                if s.status == Verdict::CompileError {
                     let pre_selector = Selector::parse(r#"pre"#).unwrap();
                     if let Some(e) = document.select(&pre_selector).last() {
-                        s.compile = Some(serde_json::json!({ "message": get_text_of_element(e) }))
+                        s.compile = Some(CompileResult {
+                            message: get_text_of_element(e),
+                        })
                     }
                 } else {
-                    if s.status == judge_status::AC {
+                    if s.status == Verdict::Accepted {
                         s.score = 100
                     }
 
@@ -253,29 +336,25 @@ impl Provider for Atcoder {
                                 .collect();
 
                             if tds.len() >= 4 {
-                                let _status = status_map(&tds[1]);
-                                cases.push(serde_json::json!({
-                                    "result": {
-                                        "type" : _status,
-                                        "scoringRate": if _status == judge_status::AC {1} else {0},
-                                        "time": extract_integer::<u32>(&tds[2]),
-                                        "memory": extract_integer::<u32>(&tds[3]),
-                                        "input": {
-                                            "name": tds[0],
-                                            "content": ""
-                                        }
-                                    },
-                                    "status": 2
-                                }))
+                                let mut detail = CaseDetail::new(
+                                    rules.resolve(&tds[1]),
+                                    extract_integer::<u32>(&tds[2]),
+                                    extract_integer::<u32>(&tds[3]),
+                                );
+                                detail.input = Some(CaseFile {
+                                    name: tds[0].clone(),
+                                    content: "".into(),
+                                });
+                                cases.push(CaseResult::done(detail))
                             }
                         });
 
-                        s.judge = Some(serde_json::json!({
-                            "subtasks": [{
-                                "score": s.score,
-                                "cases": cases
-                            }]
-                        }));
+                        s.judge = Some(JudgeDetail {
+                            subtasks: vec![Subtask {
+                                score: s.score,
+                                cases,
+                            }],
+                        });
                     }
                 }
             }
@@ -284,6 +363,57 @@ impl Provider for Atcoder {
         }
         Err(anyhow!("poll status 失败"))
     }
+
+    // 每场比赛请求一次状态接口, 测评结束的提交再通过 poll 获取测试点信息
+    async fn poll_many(
+        &self,
+        handles: &[SubmissionHandle],
+    ) -> anyhow::Result<HashMap<String, SubmissionStatus>> {
+        let mut contests: HashMap<&str, Vec<&SubmissionHandle>> = HashMap::new();
+        for handle in handles.iter().filter(|h| h.account == self.h.username) {
+            contests
+                .entry(handle.contest_id.as_deref().unwrap_or_default())
+                .or_default()
+                .push(handle);
+        }
+
+        let mut res = HashMap::new();
+        for (contest_id, handles) in contests {
+            let query: Vec<String> = handles
+                .iter()
+                .map(|h| format!("sids[]={}", h.remote_id))
+                .collect();
+            let url = format!(
+                "contests/{}/submissions/me/status/json?{}",
+                contest_id,
+                query.join("&")
+            );
+            let text = self.h.req.get(&url).await?.text().await?;
+            let infos = Atcoder::extract_statuses_from_json(&text);
+            for handle in handles {
+                // 不在返回结果中的提交单独 poll
+                let Some(info) = infos.get(&handle.remote_id) else {
+                    if let Result::Ok(s) = self.poll(handle).await {
+                        res.insert(handle.remote_id.clone(), s);
+                    }
+                    continue;
+                };
+                let status = status_rules()?.resolve(info);
+                if !status.is_pending() {
+                    if let Result::Ok(s) = self.poll(handle).await {
+                        res.insert(handle.remote_id.clone(), s);
+                    }
+                    continue;
+                }
+                let mut s = SubmissionStatus::default();
+                s.submission_id = handle.remote_id.clone();
+                s.info = info.clone();
+                s.status = status;
+                res.insert(handle.remote_id.clone(), s);
+            }
+        }
+        Ok(res)
+    }
 }
 
 // #[cfg(test)]
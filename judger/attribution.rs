@@ -0,0 +1,230 @@
+// 提交记录归属: 提交后从状态列表中找出 "这一次" 的提交, 而不是直接取最新的一行
+use std::collections::HashSet;
+use std::future::Future;
+
+// 默认提交时间窗口 (秒), 需要容忍本地与远程 oj 的时钟误差以及 codeforces 只精确到分钟
+pub const DEFAULT_WINDOW: i64 = 180;
+
+#[derive(Debug, Clone, Default)]
+pub struct Candidate {
+    pub submission_id: String,
+    pub problem: String,
+    pub lang: Option<String>,
+    pub submit_time: Option<i64>, // unix 时间戳, 秒
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Expectation {
+    pub problem: String,
+    pub lang: Option<String>,
+    pub submitted_at: i64, // 本地发起提交的 unix 时间戳, 秒
+    pub window: i64,
+    pub known_ids: HashSet<String>, // 提交前状态列表中已经存在的提交
+    pub marker: Option<String>,     // 注入到代码中的唯一标记
+}
+
+#[derive(Debug)]
+pub enum AttributionError {
+    NotFound,
+    Ambiguous(Vec<String>),
+}
+
+impl std::fmt::Display for AttributionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttributionError::NotFound => write!(f, "无法找到提交信息"),
+            AttributionError::Ambiguous(ids) => {
+                write!(f, "提交记录归属不明确, 候选: [{}]", ids.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for AttributionError {}
+
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+impl Expectation {
+    pub fn new(problem: &str, lang: Option<String>, known_ids: HashSet<String>) -> Self {
+        Self {
+            problem: problem.into(),
+            lang,
+            submitted_at: super::utils::now_timestamp(),
+            window: DEFAULT_WINDOW,
+            known_ids,
+            marker: None,
+        }
+    }
+
+    pub fn with_marker(mut self, marker: Option<String>) -> Self {
+        self.marker = marker;
+        self
+    }
+
+    pub fn matches(&self, c: &Candidate) -> bool {
+        if c.submission_id.is_empty() || self.known_ids.contains(&c.submission_id) {
+            return false;
+        }
+        if normalize(&c.problem) != normalize(&self.problem) {
+            return false;
+        }
+        if let Some(t) = c.submit_time {
+            if (t - self.submitted_at).abs() > self.window {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn lang_matches(&self, c: &Candidate) -> bool {
+        match (&self.lang, &c.lang) {
+            (Some(expected), Some(actual)) => normalize(expected) == normalize(actual),
+            _ => true,
+        }
+    }
+
+    // 语言的显示名称随 oj 更新可能对不上, 按语言筛选后没有候选时忽略语言
+    pub fn filter(&self, candidates: Vec<Candidate>) -> Vec<Candidate> {
+        let mut seen = HashSet::new();
+        let matched: Vec<Candidate> = candidates
+            .into_iter()
+            .filter(|c| self.matches(c) && seen.insert(c.submission_id.clone()))
+            .collect();
+        if !matched.iter().any(|c| self.lang_matches(c)) {
+            return matched;
+        }
+        matched
+            .into_iter()
+            .filter(|c| self.lang_matches(c))
+            .collect()
+    }
+
+    // 返回唯一匹配的 submission id, 多个候选时通过 fetch_source 获取源码并按标记区分
+    pub async fn attribute<F, Fut>(
+        &self,
+        candidates: Vec<Candidate>,
+        fetch_source: F,
+    ) -> Result<String, AttributionError>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = anyhow::Result<String>>,
+    {
+        let matched = self.filter(candidates);
+        match matched.len() {
+            0 => return Err(AttributionError::NotFound),
+            1 => return Ok(matched[0].submission_id.clone()),
+            _ => {}
+        }
+
+        let ids: Vec<String> = matched.into_iter().map(|c| c.submission_id).collect();
+        let Some(marker) = self.marker.as_ref() else {
+            return Err(AttributionError::Ambiguous(ids));
+        };
+
+        let mut hits = vec![];
+        for id in ids.iter() {
+            if let Ok(source) = fetch_source(id.clone()).await {
+                if source.contains(marker.as_str()) {
+                    hits.push(id.clone());
+                }
+            }
+        }
+        if hits.len() == 1 {
+            return Ok(hits.remove(0));
+        }
+        Err(AttributionError::Ambiguous(ids))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::judger::utils::parse_datetime;
+    use crate::judger::Codeforces;
+
+    #[tokio::test]
+    async fn test_attribute() {
+        let known = ["100".to_string()].into_iter().collect();
+        let mut exp = Expectation::new("A", None, known);
+        exp.submitted_at = parse_datetime("Oct/17/2026 12:00 UTC+8", 0).unwrap();
+
+        let html = r#"
+            <table class="status-frame-datatable">
+                <tr data-submission-id="103">
+                    <td>103</td><td><span class="format-time">Oct/17/2026 12:01</span><sup>UTC+8</sup></td><td>user</td>
+                    <td data-problemId="1"><a href="/contest/1/problem/a">A - Theatre Square</a></td><td>GNU G++17 7.3.0</td>
+                </tr>
+                <tr data-submission-id="102">
+                    <td>102</td><td><span class="format-time">Oct/17/2026 12:10</span><sup>UTC+8</sup></td><td>user</td>
+                    <td data-problemId="1"><a href="/contest/1/problem/A">A - Theatre Square</a></td><td>GNU G++17 7.3.0</td>
+                </tr>
+                <tr data-submission-id="101">
+                    <td>101</td><td><span class="format-time">Oct/17/2026 12:00</span><sup>UTC+8</sup></td><td>user</td>
+                    <td data-problemId="2"><a href="/contest/1/problem/B">B - Way Too Long Words</a></td><td>GNU G++17 7.3.0</td>
+                </tr>
+                <tr data-submission-id="100">
+                    <td>100</td><td><span class="format-time">Oct/17/2026 12:00</span><sup>UTC+8</sup></td><td>user</td>
+                    <td data-problemId="1"><a href="/contest/1/problem/A">A - Theatre Square</a></td><td>GNU G++17 7.3.0</td>
+                </tr>
+            </table>"#;
+        let candidates = Codeforces::extract_candidates_from_html(html);
+        assert_eq!(candidates[0].submit_time, Some(exp.submitted_at + 60));
+        let id = exp
+            .attribute(candidates, |_| async { Ok(String::new()) })
+            .await
+            .unwrap();
+        assert_eq!(id, "103");
+
+        // 没有标注时区时不比较时间
+        let html = r#"
+            <table class="status-frame-datatable">
+                <tr data-submission-id="104">
+                    <td>104</td><td><span class="format-time">Oct/17/2026 20:00</span></td><td>user</td>
+                    <td data-problemId="1"><a href="/contest/1/problem/A">A - Theatre Square</a></td><td>GNU G++17 7.3.0</td>
+                </tr>
+                <tr data-submission-id="103">
+                    <td>103</td><td><span class="format-time">Oct/17/2026 20:00</span></td><td>user</td>
+                    <td data-problemId="1"><a href="/contest/1/problem/A">A - Theatre Square</a></td><td>GNU G++17 7.3.0</td>
+                </tr>
+            </table>"#;
+        let candidates = Codeforces::extract_candidates_from_html(html);
+        assert!(candidates.iter().all(|c| c.submit_time.is_none()));
+        let res = exp
+            .attribute(candidates.clone(), |_| async { Ok(String::new()) })
+            .await;
+        assert!(matches!(res, Err(AttributionError::Ambiguous(_))));
+
+        let exp = exp.with_marker(Some("//42".into()));
+        let id = exp
+            .attribute(candidates, |id| async move {
+                Ok(if id == "104" { "int main(){}//42" } else { "" }.to_string())
+            })
+            .await
+            .unwrap();
+        assert_eq!(id, "104");
+
+        // 同一题目的不同语言的提交按语言区分, 语言都对不上时忽略语言
+        let html = r#"
+            <table class="status-frame-datatable">
+                <tr data-submission-id="106">
+                    <td>106</td><td><span class="format-time">Oct/17/2026 20:00</span></td><td>user</td>
+                    <td data-problemId="1"><a href="/contest/1/problem/A">A - Theatre Square</a></td><td>Python 3</td>
+                </tr>
+                <tr data-submission-id="105">
+                    <td>105</td><td><span class="format-time">Oct/17/2026 20:00</span></td><td>user</td>
+                    <td data-problemId="1"><a href="/contest/1/problem/A">A - Theatre Square</a></td><td>GNU C++17</td>
+                </tr>
+            </table>"#;
+        let candidates = Codeforces::extract_candidates_from_html(html);
+        let mut exp = Expectation::new("A", Some("GNU C++17".into()), HashSet::new());
+        assert_eq!(exp.filter(candidates.clone()).len(), 1);
+        exp.lang = Some("GNU C++20 (64)".into());
+        assert_eq!(exp.filter(candidates).len(), 2);
+    }
+}
@@ -1,83 +1,53 @@
-use super::Handler;
+use super::pool::{account_pool, AccountLease};
 use super::utils::request::PostConfig;
-use crate::global::{self, remote_judge_config, remote_judge_constant as constant};
+use super::watermark::watermark_source;
+use crate::global::{self, remote_judge_constant as constant};
 use crate::judger::utils::get_text_of_element;
 use std::collections::HashMap;
 
-use super::provider::{Problem, Provider, SubmissionStatus};
+use super::attribution::{Candidate, Expectation};
+use super::provider::{
+    CaseDetail, CaseFile, CaseResult, CompileResult, JudgeDetail, Problem, Provider,
+    SubmissionHandle, SubmissionStatus, Subtask, Verdict, VerdictRules,
+};
 use super::utils::{
     extract_integer, get_text_arr_of_children_element, get_text_arr_of_html_str,
-    get_text_of_html_str,
+    get_text_of_html_str, parse_datetime,
 };
 
 use anyhow::{anyhow, Ok};
 use hyper::HeaderMap;
 use once_cell::sync::OnceCell;
-use rand::prelude::*;
 use scraper::{ElementRef, Html, Selector};
 use simple_log::info;
-use tokio::sync::{Mutex, RwLock};
-
-async fn get_codeforces_handler() -> anyhow::Result<&'static Handler> {
-    static HANDLERS: OnceCell<Vec<Handler>> = OnceCell::new();
-    let handlers = HANDLERS.get_or_init(|| {
-        let Some(config) = remote_judge_config(constant::names::CODEFORCES) else {
-            return vec![];
-        };
-        config
-            .accounts
-            .iter()
-            .map(|account| {
-                Handler::new(
-                    account.handler.clone(),
-                    account.password.clone(),
-                    constant::base_url::CODEFORCES,
-                )
-            })
-            .collect()
-    });
-    if handlers.is_empty() {
-        return Err(anyhow!("未注册 Codeforces 账户"));
-    }
-
-    static IDX: OnceCell<Mutex<usize>> = OnceCell::new();
-    let idx = IDX.get_or_init(|| Mutex::new(0));
 
-    let mut c = idx.lock().await;
-    let now = *c;
-    *c += 1;
-    if *c >= handlers.len() {
-        *c = 0
-    }
-    Ok(&handlers[now])
+// 提交时的语言 id (programTypeId) 对应状态页面上显示的语言
+fn lang_label(lang: &str) -> Option<String> {
+    let label = match lang {
+        "4" => "FPC",
+        "7" => "Python 2",
+        "31" => "Python 3",
+        "43" => "GNU C11",
+        "50" => "GNU C++14",
+        "54" => "GNU C++17",
+        "60" => "Java 11",
+        "79" => "C# 10",
+        _ => return None,
+    };
+    Some(label.into())
 }
 
-
-fn status_map(status: &str) -> String {
-    for (&k, &v) in global::judge_status_map::CODEFORCES_STATUS_MAP.get().unwrap() {
-        if status.contains(k) {
-            return v.to_string();
-        }
-    }
-    global::judge_status::RE.into()
+fn status_rules(for_gym: bool) -> anyhow::Result<&'static VerdictRules> {
+    let name = if for_gym {
+        constant::names::GYM
+    } else {
+        constant::names::CODEFORCES
+    };
+    global::judge_status_map(name).ok_or(anyhow!("未配置 {} 的测评结果映射", name))
 } 
 
-fn records_map() -> &'static RwLock<HashMap<String, (String, String)>> {
-    static MAP: OnceCell<RwLock<HashMap<String, (String, String)>>> = OnceCell::new();
-    MAP.get_or_init(|| RwLock::new(HashMap::new()))
-}
-
-async fn record_set(submission_id: String, contest_problem: (String, String)) {
-    records_map().write().await.insert(submission_id.clone(), contest_problem);
-    tokio::spawn(async move {
-        tokio::time::sleep(tokio::time::Duration::from_secs(3600)).await;
-        let _ = records_map().write().await.remove(submission_id.as_str());
-    });
-}
-
-
 pub struct Codeforces {
-    h: &'static Handler,
+    h: AccountLease,
     for_gym: bool,
     csrf_token: tokio::sync::RwLock<String>,
 }
@@ -87,12 +57,20 @@ pub struct Codeforces {
 impl Codeforces {
     pub async fn new(for_gym: bool) -> anyhow::Result<Self> {
         Ok(Self {
-            h: get_codeforces_handler().await?,
+            h: account_pool(constant::names::CODEFORCES)?.acquire()?,
             for_gym,
             csrf_token: tokio::sync::RwLock::new(String::new()),
         })
     }
 
+    pub fn from_handle(handle: &SubmissionHandle) -> anyhow::Result<Self> {
+        Ok(Self {
+            h: account_pool(&handle.oj)?.lease(&handle.account)?,
+            for_gym: handle.oj == constant::names::GYM,
+            csrf_token: tokio::sync::RwLock::new(String::new()),
+        })
+    }
+
    pub fn get_x_csrf_token(text: &str) -> Option<String> {
         static RE: OnceCell<regex::Regex> = OnceCell::new();
         let re = RE.get_or_init(|| {
@@ -119,10 +97,21 @@ impl Codeforces {
         Ok((true, csrf_token))
     }
 
+    // 登录结果反馈给账户池, 连续失败的账户会被隔离; 登录成功后持久化会话
     pub async fn ensure_login(&self) -> anyhow::Result<String> {
+        let res = self.login().await;
+        self.h.report_login(&res);
+        if res.is_ok() {
+            self.h.save_session().await;
+        }
+        res
+    }
+
+    async fn login(&self) -> anyhow::Result<String> {
         let resp = self.h.req.get("edu/courses").await?;
         let text = resp.text().await?;
-        let(logged,csrf_token) = Codeforces::is_login(text.as_str())?;
+        // 没有 X-Csrf-Token 说明访问被拦截或处于维护页面, 熔断一段时间再试
+        let(logged,csrf_token) = Codeforces::is_login(text.as_str()).map_err(|e| self.h.req.block(e))?;
         if logged {
             return Ok(csrf_token);
         }
@@ -134,8 +123,12 @@ impl Codeforces {
         });
         let resp = self.h.req.post("enter", &data).await?;
         let text = resp.text().await?;
-        let(logged, csrf_token) = Codeforces::is_login(text.as_str())?;
-        if logged {Ok(csrf_token)} else {Err(anyhow!("登录失败"))}
+        let(logged, csrf_token) = Codeforces::is_login(text.as_str()).map_err(|e| self.h.req.block(e))?;
+        if logged {
+            return Ok(csrf_token);
+        }
+        self.h.check_banned(&text)?;
+        Err(anyhow!("登录失败"))
     }
 
     pub fn html_to_markdown(html: &str) -> String {
@@ -160,29 +153,97 @@ impl Codeforces {
     }
 
 
-    pub fn extract_submission_id_from_html(
-        html: &str,
-        index: Option<&str>,
-    ) -> anyhow::Result<String> {
+    pub fn extract_candidates_from_html(html: &str) -> Vec<Candidate> {
         let document = Html::parse_document(html);
         let selector = Selector::parse(r#"tr[data-submission-id]"#).unwrap();
-        for ele in document.select(&selector) {
-            let td_s = Selector::parse(r#"td[data-problemid] a"#).unwrap();
-            if let Some(td) = ele.select(&td_s).next() {
-                if index.is_none()
-                    || td
+        let td_s = Selector::parse("td").unwrap();
+        let problem_s = Selector::parse(r#"td[data-problemid] a"#).unwrap();
+        document
+            .select(&selector)
+            .map(|tr| {
+                let tds: Vec<_> = tr.select(&td_s).collect();
+                Candidate {
+                    submission_id: tr
                         .value()
-                        .attr("href")
-                        .unwrap_or("")
-                        .ends_with(index.unwrap())
-                {
-                    if let Some(sid) = ele.value().attr("data-submission-id") {
-                        return Ok(sid.into());
-                    }
+                        .attr("data-submission-id")
+                        .unwrap_or_default()
+                        .into(),
+                    problem: tr
+                        .select(&problem_s)
+                        .next()
+                        .and_then(|a| a.value().attr("href"))
+                        .and_then(|href| href.rsplit('/').next())
+                        .unwrap_or_default()
+                        .into(),
+                    lang: tds.get(4).map(|&td| get_text_of_element(td)),
+                    // 时间按账户设置的时区显示, 只有页面标注了时区 (UTC+8) 时才能换算
+                    // 否则不比较时间, 只按提交前已有的 submission id 与代码中的标记区分
+                    submit_time: tds
+                        .get(1)
+                        .map(|&td| get_text_of_element(td))
+                        .filter(|t| t.contains("UTC"))
+                        .and_then(|t| parse_datetime(&t, 0)),
                 }
-            }
+            })
+            .collect()
+    }
+
+    // contest/{id}/my 中各提交的测评结果, 时间 (ms) 与内存 (KB)
+    pub fn extract_verdicts_from_html(html: &str) -> HashMap<String, (String, u32, u32)> {
+        let document = Html::parse_document(html);
+        let selector = Selector::parse(r#"tr[data-submission-id]"#).unwrap();
+        let td_s = Selector::parse("td").unwrap();
+        document
+            .select(&selector)
+            .filter_map(|tr| {
+                let tds: Vec<String> = tr.select(&td_s).map(get_text_of_element).collect();
+                if tds.len() < 8 {
+                    return None;
+                }
+                let id = tr.value().attr("data-submission-id")?;
+                Some((
+                    id.to_string(),
+                    (tds[5].clone(), extract_integer(&tds[6]), extract_integer(&tds[7])),
+                ))
+            })
+            .collect()
+    }
+
+    async fn submit_source(
+        &self,
+        submission_id: &str,
+        contest_id: &str,
+    ) -> anyhow::Result<HashMap<String, String>> {
+        let data = serde_json::json!({
+            "submissionId": submission_id,
+            "csrf_token": self.csrf_token.read().await.clone()
+        });
+        let mut header_map = HeaderMap::new();
+        header_map.insert(
+            "referer",
+            format!("{}contest/{}/my", constant::base_url::CODEFORCES, contest_id).parse()?,
+        );
+
+        let config = PostConfig::new(header_map, None);
+
+        let resp = self
+            .h
+            .req
+            .post_with_config("data/submitSource", &data, config)
+            .await?;
+
+        let text = resp.text().await?;
+        let result = serde_json::from_str::<HashMap<String, String>>(&text).unwrap_or_default();
+
+        if result.is_empty() {
+            return Err(anyhow!("获取失败"));
         }
-        Err(anyhow!("无法找到提交信息"))
+        Ok(result)
+    }
+
+    async fn fetch_source(&self, submission_id: String, contest_id: &str) -> anyhow::Result<String> {
+        let result = self.submit_source(&submission_id, contest_id).await?;
+        Ok(result.get("source").cloned().unwrap_or_default())
     }
 
     pub async fn get_contest_info(
@@ -361,18 +422,25 @@ impl Provider for Codeforces {
         problem_id: &str,
         source: &str,
         lang: &str,
-    ) -> anyhow::Result<(String, serde_json::Value)> {
+    ) -> anyhow::Result<SubmissionHandle> {
         let csrf_token = self.ensure_login().await?;
         *self.csrf_token.write().await = csrf_token.clone();
         let pos = problem_id.find(|c: char| c.is_alphabetic()).unwrap_or(0);
-        let rand_num: u64 = rand::thread_rng().gen::<u64>();
+        let oj = if self.for_gym {
+            constant::names::GYM
+        } else {
+            constant::names::CODEFORCES
+        };
+        // codeforces 会拒绝重复的代码, 加上水印保证每次提交都不相同
+        let (source, watermark) = watermark_source(oj, lang, source);
+        let marker = watermark.map(|w| w.value);
         let contest_id = &problem_id[..pos];
         let peoblem_idx = &problem_id[pos..];
 
         let data = serde_json::json!({
             "action": "submitSolutionFormSubmitted",
             "tabSize": 4,
-            "source": format!("{}//{}", source, rand_num),
+            "source": source,
             "sourceFile": "",
             "contestId": contest_id,
             "submittedProblemIndex": peoblem_idx,
@@ -380,8 +448,13 @@ impl Provider for Codeforces {
             "csrf_token": csrf_token
         });
 
-        self.h.accquire(contest_id).await;
-        let future = || async {
+        let list_url = &format!(
+            "{}/{}/my",
+            if self.for_gym { "gym" } else { "contest" },
+            contest_id
+        );
+
+        let future = |expectation: Expectation| async move {
             let resp = self
                 .h
                 .req
@@ -395,66 +468,73 @@ impl Provider for Codeforces {
                 )
                 .await?;
 
-            if !resp.status().is_success() {
+            // 被封禁的账户提交时返回的页面中有提示
+            let status = resp.status();
+            self.h.check_banned(&resp.text().await?)?;
+            if !status.is_success() {
                 return Err(anyhow!("提交代码失败"));
             }
-            let reps2 = self.h.req.get(&format!("contest/{}/my", contest_id)).await?;
+            let reps2 = self.h.req.get(list_url).await?;
             let html = reps2.text().await?;
-            Codeforces::extract_submission_id_from_html(&html, None)
+            let candidates = Codeforces::extract_candidates_from_html(&html);
+            Ok(expectation
+                .attribute(candidates, |sid| self.fetch_source(sid, contest_id))
+                .await?)
         };
-        
-        self.h.release(contest_id).await;
-        let res = future().await?;
-        record_set(res.clone(), (contest_id.into(), peoblem_idx.into())).await;
-        let value = serde_json::json!({"submissionId": res,  "account": self.h.username});
-        Ok((res, value))
-    }
 
-    async fn poll(&self, submission_id: &str) -> anyhow::Result<SubmissionStatus> {
-        let data = serde_json::json!({
-            "submissionId": submission_id,
-            "csrf_token": self.csrf_token.read().await.clone()
-        });
-        let header_map = {
-            let mp = records_map().read().await;
-            let x = mp.get(submission_id);
-            let mut header_map = HeaderMap::new();
-            header_map.insert("referer", format!("{}contest/{}/my", constant::base_url::CODEFORCES, x.unwrap().0 ).parse().unwrap());
-            header_map
+        let res = {
+            let _permit = self.h.accquire(contest_id).await;
+            let html = self.h.req.get(list_url).await?.text().await?;
+            let known_ids = Codeforces::extract_candidates_from_html(&html)
+                .into_iter()
+                .map(|c| c.submission_id)
+                .collect();
+            let expectation =
+                Expectation::new(peoblem_idx, lang_label(lang), known_ids).with_marker(marker.clone());
+            future(expectation).await?
         };
+        Ok(SubmissionHandle {
+            oj: oj.into(),
+            account: self.h.username.clone(),
+            contest_id: Some(contest_id.into()),
+            problem_id: problem_id.into(),
+            remote_id: res,
+            marker,
+        })
+    }
 
-        let config = PostConfig::new(header_map, None);
-        
-        let resp = self.h.req.post_with_config("data/submitSource", &data, config).await?;
-
-        let text = resp.text().await?;
-        let result = serde_json::from_str::<HashMap<String, String>>(&text).unwrap_or_default();
-
-        if result.is_empty() {
-            return Err(anyhow!("获取失败"));
+    async fn poll(&self, handle: &SubmissionHandle) -> anyhow::Result<SubmissionStatus> {
+        if handle.account != self.h.username {
+            return Err(anyhow!("提交记录不属于当前账户"));
+        }
+        // 新进程中还没有 csrf_token, 需要先登录获取
+        if self.csrf_token.read().await.is_empty() {
+            let csrf_token = self.ensure_login().await?;
+            *self.csrf_token.write().await = csrf_token;
         }
+        let submission_id = handle.remote_id.as_str();
+        let contest_id = handle.contest_id.as_deref().unwrap_or_default();
+        let result = self.submit_source(submission_id, contest_id).await?;
 
         let verdict = get_text_of_html_str(result.get("verdict").unwrap_or(&"".into()));
         if verdict.as_str() == "" {
             return Err(anyhow!("获取失败"));
         }
 
-        let judge_status = status_map(&verdict);
+        let field = |key: &str| result.get(key).cloned();
+        let judge_status = status_rules(self.for_gym)?.resolve(&verdict);
         let mut status = SubmissionStatus::default();
         status.submission_id = submission_id.into();
-        status.is_over = !(["Running", "Pending", "queue"]
-            .iter()
-            .any(|&s| verdict.contains(s)));
+        status.is_over = !judge_status.is_pending();
         status.status = judge_status;
         status.info = verdict;
         if status.is_over {
-            if status.status == global::judge_status::CE {
-                status.compile = Some(serde_json::json!({
-                    "message": result.get("checkerStdoutAndStderr#1")
-                }))
-
+            if status.status == Verdict::CompileError {
+                status.compile = Some(CompileResult {
+                    message: field("checkerStdoutAndStderr#1").unwrap_or_default(),
+                })
             } else {
-                if status.status == global::judge_status::AC {
+                if status.status == Verdict::Accepted {
                     status.score = 100
                 }
 
@@ -469,6 +549,7 @@ impl Provider for Codeforces {
 
                     let mut cases = vec![];
                     for i in 1..count + 1 {
+                        // memoryConsumed 单位为字节
                         let _memory: u32 = result
                             .get(&format!("memoryConsumed#{}", i))
                             .unwrap_or(&"0".into())
@@ -482,41 +563,85 @@ impl Provider for Codeforces {
 
                         time = std::cmp::max(time, _time);
                         memory = std::cmp::max(memory, _memory);
-                        cases.push(serde_json::json!({
-                            "status": 2, 
-                            "result": {
-                                "type": if i < count { global::judge_status::AC.to_string() } else {status.status.clone()},
-                                "scoringRate": if i < count {1} else { status.score / 100 },
-                                "memory": _memory,
-                                "time": _time,
-                                "input": {
-                                    "name": "---",
-                                    "content": result.get(&format!("input#{}", i)),
-                                },
-                                "output": {
-                                    "name": "---",
-                                    "content": result.get(&format!("answer#{}", i)),
-                                },
-                                "userOutput": result.get(&format!("output#{}", i)),
-                                "spjMessage": result.get(&format!("checkerStdoutAndStderr#{}", i))
-                            }   
-                        }));
-                    }
 
+                        // 只有最后一个测试点可能不通过
+                        let verdict = if i < count { Verdict::Accepted } else { status.status.clone() };
+                        let mut detail = CaseDetail::new(verdict, _time, _memory);
+                        detail.input = Some(CaseFile {
+                            name: "---".into(),
+                            content: field(&format!("input#{}", i)).unwrap_or_default(),
+                        });
+                        detail.output = Some(CaseFile {
+                            name: "---".into(),
+                            content: field(&format!("answer#{}", i)).unwrap_or_default(),
+                        });
+                        detail.user_output = field(&format!("output#{}", i));
+                        detail.spj_message = field(&format!("checkerStdoutAndStderr#{}", i));
+                        cases.push(CaseResult::done(detail));
+                    }
 
                     status.time = time;
                     status.memory = memory;
-                    status.judge = Some(serde_json::json!({
-                        "subtasks": [{
-                            "score": status.score,
-                            "cases": cases
-                        }]
-                    }));
+                    status.judge = Some(JudgeDetail {
+                        subtasks: vec![Subtask {
+                            score: status.score,
+                            cases,
+                        }],
+                    });
                 }
             }
         }
         Ok(status)
     }
+
+    // 每场比赛请求一次 contest/{id}/my, 测评结束的提交再通过 poll 获取测试点信息
+    async fn poll_many(
+        &self,
+        handles: &[SubmissionHandle],
+    ) -> anyhow::Result<HashMap<String, SubmissionStatus>> {
+        let mut contests: HashMap<&str, Vec<&SubmissionHandle>> = HashMap::new();
+        for handle in handles.iter().filter(|h| h.account == self.h.username) {
+            contests
+                .entry(handle.contest_id.as_deref().unwrap_or_default())
+                .or_default()
+                .push(handle);
+        }
+
+        let mut res = HashMap::new();
+        for (contest_id, handles) in contests {
+            let list_url = format!(
+                "{}/{}/my",
+                if self.for_gym { "gym" } else { "contest" },
+                contest_id
+            );
+            let html = self.h.req.get(&list_url).await?.text().await?;
+            let verdicts = Codeforces::extract_verdicts_from_html(&html);
+            for handle in handles {
+                // 只读取了列表的第一页, 不在列表中的提交单独 poll
+                let Some((verdict, time, memory)) = verdicts.get(&handle.remote_id) else {
+                    if let Result::Ok(status) = self.poll(handle).await {
+                        res.insert(handle.remote_id.clone(), status);
+                    }
+                    continue;
+                };
+                let judge_status = status_rules(self.for_gym)?.resolve(verdict);
+                if !judge_status.is_pending() {
+                    if let Result::Ok(status) = self.poll(handle).await {
+                        res.insert(handle.remote_id.clone(), status);
+                    }
+                    continue;
+                }
+                let mut status = SubmissionStatus::default();
+                status.submission_id = handle.remote_id.clone();
+                status.status = judge_status;
+                status.info = verdict.clone();
+                status.time = *time;
+                status.memory = *memory;
+                res.insert(handle.remote_id.clone(), status);
+            }
+        }
+        Ok(res)
+    }
 }
 
 // mod tests {
@@ -1,26 +1,71 @@
+use super::provider::VerdictRule;
+use super::utils::now_timestamp;
+use super::utils::breaker::BreakerConfig;
+use super::utils::rate_limit::RateLimitConfig;
 use super::utils::request::RemoteJudgeRequest;
+use super::utils::session::{session_store, StoredSession};
+use cookie_store::Cookie;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use tokio::sync::{Mutex, Notify};
+use simple_log::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Account {
     pub handler: String,
     pub password: String,
+    #[serde(default)]
+    pub banned: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct RemoteJudgeConfig {
     pub lang_map: HashMap<String, String>,
     pub accounts: Vec<Account>,
+    #[serde(default = "default_session_ttl")]
+    pub session_ttl: u64, // 持久化会话的有效期, 秒
+    #[serde(default)]
+    pub verdict_rules: Vec<VerdictRule>, // 优先于默认规则匹配
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig, // 不配置时不限流
+    #[serde(default)]
+    pub breaker: BreakerConfig,
+    #[serde(default)]
+    pub ban_markers: Vec<String>, // 追加在默认的封禁提示之后
+}
+
+fn default_session_ttl() -> u64 {
+    3 * 24 * 3600
 }
 
 pub struct Handler {
     pub username: String,
     pub password: String,
     pub req: RemoteJudgeRequest,
-    pub mtx: Mutex<HashSet<String>>,
-    pub notify: Notify,
+    keys: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    saved_cookies: std::sync::Mutex<Vec<Cookie<'static>>>, // 最近一次持久化的 cookie
+}
+
+// 持有期间独占 handler 上的某个 key, drop 时 (包括出错提前返回或 panic) 自动释放
+pub struct HandlerPermit<'a> {
+    h: &'a Handler,
+    key: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for HandlerPermit<'_> {
+    fn drop(&mut self) {
+        self.guard.take();
+        let mut keys = self.h.keys.lock().unwrap_or_else(|e| e.into_inner());
+        // 没有其他等待者时清理该 key, 避免 map 无限增长
+        if keys
+            .get(&self.key)
+            .map_or(false, |m| Arc::strong_count(m) == 1)
+        {
+            keys.remove(&self.key);
+        }
+    }
 }
 
 impl Handler {
@@ -29,29 +74,76 @@ impl Handler {
             username,
             password,
             req: RemoteJudgeRequest::new(url),
-            mtx: Mutex::new(HashSet::new()),
-            notify: Notify::new(),
+            keys: std::sync::Mutex::new(HashMap::new()),
+            saved_cookies: std::sync::Mutex::new(vec![]),
         }
     }
 
-    pub async fn accquire(&self, key: &str) {
-        loop {
-            let mut guard = self.mtx.lock().await;
-            if guard.contains(key) {
-                drop(guard);
-                self.notify.notified().await;
-            } else {
-                guard.insert(key.into());
-                break;
+    fn session_key(&self) -> String {
+        format!("{}_{}", self.req.host(), self.username)
+    }
+
+    // 载入持久化的会话, 过期的 cookie 直接丢弃, 全部过期时之后走密码登录
+    pub fn restore_session(&self, ttl: u64) -> bool {
+        let store = session_store();
+        let key = self.session_key();
+        let Some(session) = store.load(&key) else {
+            return false;
+        };
+        let cookies = session.into_cookies(ttl);
+        if cookies.is_empty() {
+            store.remove(&key);
+            return false;
+        }
+        self.req.import_cookies(cookies.clone());
+        *self.saved_cookies.lock().unwrap_or_else(|e| e.into_inner()) = cookies;
+        true
+    }
+
+    // 每次确认登录后调用, 只在 cookie 变化时写入, 写入放到阻塞线程中执行
+    pub async fn save_session(&self) {
+        let cookies = self.req.export_cookies();
+        {
+            let mut saved = self.saved_cookies.lock().unwrap_or_else(|e| e.into_inner());
+            if cookies.is_empty()
+                || (saved.len() == cookies.len() && cookies.iter().all(|c| saved.contains(c)))
+            {
+                return;
             }
+            *saved = cookies.clone();
+        }
+        let key = self.session_key();
+        let session = StoredSession {
+            cookies,
+            saved_at: now_timestamp(),
+        };
+        let res = tokio::task::spawn_blocking(move || session_store().save(&key, &session))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|res| res);
+        if let Err(e) = res {
+            // 下次确认登录时重新写入
+            self.saved_cookies
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clear();
+            info!("保存账户 {} 会话失败: {}", self.username, e);
         }
     }
 
-    pub async fn release(&self, key: &str) {
-        let mut guard = self.mtx.lock().await;
-        guard.remove(key);
-        self.notify.notify_one();
+    // 同一账户下同一 key 的 "提交 -> 查找 submission id" 过程需要串行执行
+    pub async fn accquire(&self, key: &str) -> HandlerPermit<'_> {
+        let mtx = self
+            .keys
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        HandlerPermit {
+            h: self,
+            key: key.to_string(),
+            guard: Some(mtx.lock_owned().await),
+        }
     }
 }
-
-
@@ -1,64 +1,52 @@
-use super::provider::{Problem, Provider, SubmissionStatus};
-use super::utils::{extract_integer, get_text_arr_of_children_element, get_text_of_element};
-use super::Handler;
-
-use crate::global::{self, judge_status, remote_judge_config, remote_judge_constant as constant};
+use super::attribution::{Candidate, Expectation};
+use super::provider::{
+    CompileResult, Problem, Provider, SubmissionHandle, SubmissionStatus, Verdict, VerdictRules,
+};
+use super::utils::{
+    extract_integer, get_text_arr_of_children_element, get_text_of_element, parse_datetime,
+};
+use super::watermark::watermark_source;
+use super::pool::{account_pool, AccountLease};
+
+use crate::global::{self, remote_judge_constant as constant};
 use anyhow::{anyhow, Ok};
-use once_cell::sync::OnceCell;
 use scraper::{ElementRef, Html, Selector};
-use tokio::sync::Mutex;
+use std::collections::HashMap;
 
-async fn get_hdu_handler() -> anyhow::Result<&'static Handler> {
-    static HANDLERS: OnceCell<Vec<Handler>> = OnceCell::new();
-    let handlers = HANDLERS.get_or_init(|| {
-        let Some(config) = remote_judge_config(constant::names::HDU) else {
-            return vec![];
-        };
-        config
-            .accounts
-            .iter()
-            .map(|account| {
-                Handler::new(
-                    account.handler.clone(),
-                    account.password.clone(),
-                    constant::base_url::HDU,
-                )
-            })
-            .collect()
-    });
-    if handlers.is_empty() {
-        return Err(anyhow!("未注册 hdu 账户"));
-    }
-
-    static IDX: OnceCell<Mutex<usize>> = OnceCell::new();
-    let idx = IDX.get_or_init(|| Mutex::new(0));
-
-    let mut c = idx.lock().await;
-    let now = *c;
-    *c += 1;
-    if *c >= handlers.len() {
-        *c = 0
-    }
-    Ok(&handlers[now])
+fn status_rules() -> anyhow::Result<&'static VerdictRules> {
+    global::judge_status_map(constant::names::HDU)
+        .ok_or(anyhow!("未配置 {} 的测评结果映射", constant::names::HDU))
 }
 
-fn status_map(status: &str) -> String {
-    for (&k, &v) in global::judge_status_map::HDU_STATUS_MAP.get().unwrap() {
-        if status.contains(k) {
-            return v.to_string();
-        }
-    }
-    global::judge_status::RE.into()
+// 提交时的语言 id 对应状态页面上显示的语言
+fn lang_label(lang: &str) -> Option<String> {
+    let label = match lang {
+        "0" => "G++",
+        "1" => "GCC",
+        "2" => "C++",
+        "3" => "C",
+        "4" => "Pascal",
+        "5" => "Java",
+        "6" => "C#",
+        _ => return None,
+    };
+    Some(label.into())
 }
 
 pub struct Hdu {
-    h: &'static Handler,
+    h: AccountLease,
 }
 
 impl Hdu {
     pub async fn new() -> anyhow::Result<Self> {
         Ok(Self {
-            h: get_hdu_handler().await?,
+            h: account_pool(constant::names::HDU)?.acquire()?,
+        })
+    }
+
+    pub fn from_handle(handle: &SubmissionHandle) -> anyhow::Result<Self> {
+        Ok(Self {
+            h: account_pool(&handle.oj)?.lease(&handle.account)?,
         })
     }
 
@@ -88,12 +76,26 @@ impl Hdu {
         let resp = self.h.req.get("index.php").await?;
         let text = resp.text().await?;
         if !text.contains(&self.h.username) {
+            // 未登录时首页有登录链接, 都没有说明访问被拦截或正在维护, 熔断一段时间再试
+            if !text.contains("userloginex.php") {
+                return Err(self.h.req.block("hdu 页面异常, 可能正在维护或访问已经被拦截"));
+            }
             return Err(anyhow!("未登录"));
         }
         Ok(())
     }
 
+    // 登录结果反馈给账户池, 连续失败的账户会被隔离; 登录成功后持久化会话
     pub async fn ensure_login(&self) -> anyhow::Result<()> {
+        let res = self.login().await;
+        self.h.report_login(&res);
+        if res.is_ok() {
+            self.h.save_session().await;
+        }
+        res
+    }
+
+    async fn login(&self) -> anyhow::Result<()> {
         if self.is_login().await.is_ok() {
             return Ok(());
         }
@@ -104,45 +106,97 @@ impl Hdu {
             "login": "Sign In"
         });
 
-        self.h
+        let text = self
+            .h
             .req
             .post("userloginex.php?action=login", &data)
+            .await?
+            .text()
             .await?;
-        self.is_login().await
+        let res = self.is_login().await;
+        if res.is_err() {
+            self.h.check_banned(&text)?;
+        }
+        res
     }
 
     pub async fn extract_submission_status_from_html(
         html: &str,
     ) -> anyhow::Result<SubmissionStatus> {
+        Hdu::extract_submission_statuses_from_html(html)?
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("获取 submission status 失败"))
+    }
+
+    // status.php 列表中的全部提交
+    pub fn extract_submission_statuses_from_html(
+        html: &str,
+    ) -> anyhow::Result<Vec<SubmissionStatus>> {
+        let rules = status_rules()?;
         let document = Html::parse_document(html);
         let selector = Selector::parse(r#"div[id="fixed_table"] table tbody tr"#).unwrap();
-        let mut iter = document.select(&selector);
-        iter.next();
-        if let Some(ele) = iter.next() {
-            let v = get_text_arr_of_children_element(ele);
-            if v.len() < 8 {
-                return Err(anyhow!("获取 submission status 失败"));
-            }
+        Ok(document
+            .select(&selector)
+            .skip(1)
+            .filter_map(|ele| {
+                let v = get_text_arr_of_children_element(ele);
+                if v.len() < 8 {
+                    return None;
+                }
 
-            let status = status_map(&v[2]);
-            let res = SubmissionStatus {
-                submission_id: v[0].clone(),
-                status: status.clone(),
-                info: status.clone(),
-                is_over: !(["Running", "Que", "Pending", "Compiling"]
-                    .iter()
-                    .any(|&s| v[2].contains(s))),
-                score: if status == judge_status::AC { 100 } else { 0 },
-                time: extract_integer(&v[4]),
-                memory: extract_integer(&v[5]),
-                compile: None,
-                judge: None,
-            };
-
-            return Ok(res);
-        }
+                let status = rules.resolve(&v[2]);
+                Some(SubmissionStatus {
+                    submission_id: v[0].clone(),
+                    info: status.to_string(),
+                    is_over: !status.is_pending(),
+                    score: if status == Verdict::Accepted { 100 } else { 0 },
+                    status,
+                    time: extract_integer(&v[4]),
+                    memory: extract_integer(&v[5]),
+                    compile: None,
+                    judge: None,
+                })
+            })
+            .collect())
+    }
+
+    pub fn extract_candidates_from_html(html: &str) -> Vec<Candidate> {
+        let document = Html::parse_document(html);
+        let selector = Selector::parse(r#"div[id="fixed_table"] table tbody tr"#).unwrap();
+        let td_s = Selector::parse("td").unwrap();
+        document
+            .select(&selector)
+            .skip(1)
+            .filter_map(|tr| {
+                let tds: Vec<String> = tr.select(&td_s).map(get_text_of_element).collect();
+                if tds.len() < 9 {
+                    return None;
+                }
+                Some(Candidate {
+                    submission_id: tds[0].clone(),
+                    problem: tds[3].clone(),
+                    lang: Some(tds[7].clone()),
+                    // hdu 显示北京时间
+                    submit_time: parse_datetime(&tds[1], 8 * 3600),
+                })
+            })
+            .collect()
+    }
 
-        Err(anyhow!("获取 submission status 失败"))
+    async fn fetch_source(&self, submission_id: String) -> anyhow::Result<String> {
+        let resp = self
+            .h
+            .req
+            .get(&format!("viewcode.php?rid={}", submission_id))
+            .await?;
+        let html = &resp.text().await?;
+        let document = Html::parse_document(html);
+        let selector = Selector::parse(r#"textarea[id="usercode"]"#).unwrap();
+        match document.select(&selector).next() {
+            Some(x) => Ok(x.text().collect()),
+            None => Err(anyhow!("获取源码失败")),
+        }
     }
 
     pub async fn get_compile_info(&self, submission_id: &str) -> anyhow::Result<String> {
@@ -230,46 +284,63 @@ impl Provider for Hdu {
         problem_id: &str,
         source: &str,
         lang: &str,
-    ) -> anyhow::Result<(String, serde_json::Value)> {
+    ) -> anyhow::Result<SubmissionHandle> {
         self.ensure_login().await?;
+        let (source, watermark) = watermark_source(constant::names::HDU, lang, source);
+        let marker = watermark.map(|w| w.value);
 
         let data = serde_json::json!({
             "check": 0,
             "problemid": problem_id,
             "language": lang,
-            "_usercode": base64_url::encode(urlencoding::encode(source).as_bytes())
+            "_usercode": base64_url::encode(urlencoding::encode(&source).as_bytes())
         });
 
-        self.h.accquire(problem_id).await;
-        let furure = || async {
+        let list_url = &format!("status.php?user={}&pid={}", self.h.username, problem_id);
+
+        let furure = |expectation: Expectation| async move {
             let resp = self.h.req.post("submit.php?action=submit", &data).await?;
 
             let text = &resp.text().await?;
             if !text.contains("Realtime Status") {
+                self.h.check_banned(text)?;
                 return Err(anyhow!("提交失败"));
             }
 
-            let resp = self
-                .h
-                .req
-                .get(&format!(
-                    "status.php?user={}&pid={}",
-                    self.h.username, problem_id
-                ))
-                .await?;
+            let resp = self.h.req.get(list_url).await?;
             let text = resp.text().await?;
-            let res = Hdu::extract_submission_status_from_html(&text).await?;
-
-            Ok(res.submission_id)
+            let candidates = Hdu::extract_candidates_from_html(&text);
+            Ok(expectation
+                .attribute(candidates, |rid| self.fetch_source(rid))
+                .await?)
+        };
+        let res = {
+            let _permit = self.h.accquire(problem_id).await;
+            let text = self.h.req.get(list_url).await?.text().await?;
+            let known_ids = Hdu::extract_candidates_from_html(&text)
+                .into_iter()
+                .map(|c| c.submission_id)
+                .collect();
+            let expectation =
+                Expectation::new(problem_id, lang_label(lang), known_ids).with_marker(marker.clone());
+            furure(expectation).await?
         };
-        self.h.release(problem_id).await;
-        let res = furure().await?;
 
-        let value = serde_json::json!({"submissionId": res,  "account": self.h.username});
-        Ok((res, value))
+        Ok(SubmissionHandle {
+            oj: constant::names::HDU.into(),
+            account: self.h.username.clone(),
+            contest_id: None,
+            problem_id: problem_id.into(),
+            remote_id: res,
+            marker,
+        })
     }
 
-    async fn poll(&self, submission_id: &str) -> anyhow::Result<SubmissionStatus> {
+    async fn poll(&self, handle: &SubmissionHandle) -> anyhow::Result<SubmissionStatus> {
+        if handle.account != self.h.username {
+            return Err(anyhow!("提交记录不属于当前账户"));
+        }
+        let submission_id = handle.remote_id.as_str();
         let resp = self
             .h
             .req
@@ -282,14 +353,59 @@ impl Provider for Hdu {
         }
 
         let mut s = Hdu::extract_submission_status_from_html(html.as_str()).await?;
+        if s.submission_id != submission_id {
+            return Err(anyhow!("获取 Status 失败"));
+        }
 
-        if s.status.as_str() == judge_status::CE {
-            s.compile = Some(serde_json::json!({
-                "message": self.get_compile_info(submission_id).await.unwrap_or_default()
-            }))
+        if s.status == Verdict::CompileError {
+            s.compile = Some(CompileResult {
+                message: self.get_compile_info(submission_id).await.unwrap_or_default(),
+            })
         }
         Ok(s)
     }
+
+    // status.php?user= 列出账户最近的提交, 不在列表中的较早提交单独 poll
+    async fn poll_many(
+        &self,
+        handles: &[SubmissionHandle],
+    ) -> anyhow::Result<HashMap<String, SubmissionStatus>> {
+        let resp = self
+            .h
+            .req
+            .get(&format!("status.php?user={}", self.h.username))
+            .await?;
+        let html = resp.text().await?;
+        if !html.contains("Realtime Status") {
+            return Err(anyhow!("获取 Status 失败"));
+        }
+        let mut statuses: HashMap<String, SubmissionStatus> =
+            Hdu::extract_submission_statuses_from_html(&html)?
+                .into_iter()
+                .map(|s| (s.submission_id.clone(), s))
+                .collect();
+
+        let mut res = HashMap::new();
+        for handle in handles.iter().filter(|h| h.account == self.h.username) {
+            let sid = handle.remote_id.as_str();
+            match statuses.remove(sid) {
+                Some(mut s) => {
+                    if s.status == Verdict::CompileError {
+                        s.compile = Some(CompileResult {
+                            message: self.get_compile_info(sid).await.unwrap_or_default(),
+                        })
+                    }
+                    res.insert(handle.remote_id.clone(), s);
+                }
+                None => {
+                    if let Result::Ok(s) = self.poll(handle).await {
+                        res.insert(handle.remote_id.clone(), s);
+                    }
+                }
+            }
+        }
+        Ok(res)
+    }
 }
 
 // #[cfg(test)]
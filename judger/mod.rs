@@ -1,9 +1,12 @@
 mod atcoder;
+pub mod attribution;
 mod codeforces;
 mod definition;
 mod hdu;
+pub mod pool;
 pub mod provider;
 pub mod utils;
+pub mod watermark;
 pub use atcoder::Atcoder;
 pub use codeforces::Codeforces;
 pub use definition::{Account, Handler, RemoteJudgeConfig};
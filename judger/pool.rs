@@ -0,0 +1,350 @@
+// 各 oj 共用的账户池: 记录账户健康状态与进行中的提交数, 优先分配负载最低的健康账户
+use super::utils::breaker::{breaker, CircuitOpen};
+use super::utils::rate_limit::RateLimiter;
+use super::Handler;
+use crate::global::{remote_judge_config, remote_judge_constant as constant};
+
+use anyhow::anyhow;
+use once_cell::sync::OnceCell;
+use scraper::{Html, Selector};
+use serde::Serialize;
+use simple_log::info;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 连续登录失败达到该次数后隔离账户
+const MAX_LOGIN_FAILURES: u32 = 3;
+// 首次隔离时长, 之后每次翻倍, 最长 MAX_COOLDOWN
+const BASE_COOLDOWN: Duration = Duration::from_secs(300);
+const MAX_COOLDOWN: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountState {
+    Unknown,
+    LoggedIn,
+    Failing,
+    Banned,
+    CoolingDown,
+}
+
+#[derive(Debug)]
+struct Health {
+    state: AccountState,
+    failures: u32,
+    quarantines: u32,
+    cooldown_until: Option<Instant>,
+}
+
+// 登录或提交失败时, 页面的错误提示中出现这些文字说明账户可能被封禁
+fn default_ban_markers(name: &str) -> &'static [&'static str] {
+    match name {
+        constant::names::CODEFORCES => &["is blocked", "has been blocked"],
+        constant::names::ATCODER => &["is banned", "has been banned"],
+        constant::names::HDU => &["has been banned", "has been disabled"],
+        _ => &[],
+    }
+}
+
+// 各 oj 页面上显示错误提示的元素, 只在其中查找封禁提示, 避免误匹配题面等正文
+fn notice_selector(name: &str) -> &'static str {
+    match name {
+        constant::names::CODEFORCES => "span.error",
+        constant::names::ATCODER => "div.alert-danger, div.alert-warning",
+        constant::names::HDU => r#"font[color="red"]"#,
+        _ => "",
+    }
+}
+
+pub struct PooledAccount {
+    pub handler: Handler,
+    health: Mutex<Health>,
+    in_flight: AtomicUsize,
+    ban_markers: Vec<String>,
+    notice_selector: &'static str,
+}
+
+impl PooledAccount {
+    fn new(
+        handler: Handler,
+        banned: bool,
+        ban_markers: Vec<String>,
+        notice_selector: &'static str,
+    ) -> Self {
+        Self {
+            handler,
+            ban_markers,
+            notice_selector,
+            health: Mutex::new(Health {
+                state: if banned {
+                    AccountState::Banned
+                } else {
+                    AccountState::Unknown
+                },
+                failures: 0,
+                quarantines: 0,
+                cooldown_until: None,
+            }),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    // 冷却结束的账户恢复为 Unknown, 等待下一次登录确认
+    pub fn state(&self) -> AccountState {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        if health.state == AccountState::CoolingDown
+            && health.cooldown_until.map_or(true, |t| t <= Instant::now())
+        {
+            health.state = AccountState::Unknown;
+            health.failures = 0;
+            health.cooldown_until = None;
+        }
+        health.state
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    fn is_available(&self) -> bool {
+        !matches!(
+            self.state(),
+            AccountState::Banned | AccountState::CoolingDown
+        )
+    }
+
+    pub fn report_login(&self, ok: bool) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        if health.state == AccountState::Banned {
+            return;
+        }
+        // 隔离中的账户只有登录成功才提前恢复
+        if !ok && health.state == AccountState::CoolingDown {
+            return;
+        }
+        if ok {
+            health.state = AccountState::LoggedIn;
+            health.failures = 0;
+            health.quarantines = 0;
+            return;
+        }
+
+        health.failures += 1;
+        health.state = AccountState::Failing;
+        if health.failures >= MAX_LOGIN_FAILURES {
+            let cooldown = std::cmp::min(
+                BASE_COOLDOWN * 2u32.pow(health.quarantines.min(8)),
+                MAX_COOLDOWN,
+            );
+            health.state = AccountState::CoolingDown;
+            health.cooldown_until = Some(Instant::now() + cooldown);
+            health.quarantines += 1;
+            info!(
+                "账户 {} 连续登录失败 {} 次, 隔离 {} 秒",
+                self.handler.username,
+                health.failures,
+                cooldown.as_secs()
+            );
+        }
+    }
+
+    // 疑似被封禁的账户按最长时间隔离, 冷却结束后或登录成功时恢复
+    fn suspend(&self, reason: &str) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        if health.state == AccountState::Banned {
+            return;
+        }
+        health.state = AccountState::CoolingDown;
+        health.cooldown_until = Some(Instant::now() + MAX_COOLDOWN);
+        health.quarantines += 1;
+        info!(
+            "账户 {} 疑似被封禁 ({}), 隔离 {} 秒",
+            self.handler.username,
+            reason,
+            MAX_COOLDOWN.as_secs()
+        );
+    }
+
+    fn check_banned(&self, html: &str) -> anyhow::Result<()> {
+        let Result::Ok(selector) = Selector::parse(self.notice_selector) else {
+            return Ok(());
+        };
+        let document = Html::parse_document(html);
+        let notice = document
+            .select(&selector)
+            .map(|e| e.text().collect::<String>())
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        let Some(marker) = self
+            .ban_markers
+            .iter()
+            .find(|m| notice.contains(&m.to_lowercase()))
+        else {
+            return Ok(());
+        };
+        self.suspend(marker);
+        Err(anyhow!(
+            "账户 {} 疑似被封禁: {}",
+            self.handler.username,
+            marker
+        ))
+    }
+}
+
+// 持有期间计入账户的进行中提交数, drop 时释放
+pub struct AccountLease {
+    account: &'static PooledAccount,
+}
+
+impl AccountLease {
+    fn new(account: &'static PooledAccount) -> Self {
+        account.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self { account }
+    }
+
+    // 熔断说明远程 oj 不可用, 不是账户的问题, 不计入登录结果
+    pub fn report_login<T>(&self, res: &anyhow::Result<T>) {
+        if res.as_ref().is_err_and(|e| e.is::<CircuitOpen>()) {
+            return;
+        }
+        self.account.report_login(res.is_ok())
+    }
+
+    // 登录或提交失败时检查返回页面的错误提示, 疑似被封禁的账户隔离一段时间
+    pub fn check_banned(&self, text: &str) -> anyhow::Result<()> {
+        self.account.check_banned(text)
+    }
+}
+
+impl std::ops::Deref for AccountLease {
+    type Target = Handler;
+
+    fn deref(&self) -> &Handler {
+        &self.account.handler
+    }
+}
+
+impl Drop for AccountLease {
+    fn drop(&mut self) {
+        self.account.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct AccountPool {
+    name: &'static str,
+    accounts: Vec<PooledAccount>,
+    next: AtomicUsize,
+}
+
+impl AccountPool {
+    fn new(name: &'static str, base_url: &'static str) -> Self {
+        let accounts = match remote_judge_config(name) {
+            None => vec![],
+            Some(config) => config
+                .accounts
+                .iter()
+                .map(|account| {
+                    let mut handler =
+                        Handler::new(account.handler.clone(), account.password.clone(), base_url);
+                    let limiter =
+                        RateLimiter::new(handler.req.host(), &account.handler, &config.rate_limit);
+                    handler.req.set_rate_limiter(limiter);
+                    handler.req.set_breaker(breaker(name, &config.breaker));
+                    if handler.restore_session(config.session_ttl) {
+                        info!("账户 {} 复用已保存的会话", account.handler);
+                    }
+                    let ban_markers = default_ban_markers(name)
+                        .iter()
+                        .map(|m| m.to_string())
+                        .chain(config.ban_markers.iter().cloned())
+                        .collect();
+                    PooledAccount::new(handler, account.banned, ban_markers, notice_selector(name))
+                })
+                .collect(),
+        };
+        Self {
+            name,
+            accounts,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    // 选择进行中提交最少的可用账户, 负载相同时轮流分配
+    pub fn acquire(&'static self) -> anyhow::Result<AccountLease> {
+        if self.accounts.is_empty() {
+            return Err(anyhow!("未注册 {} 账户", self.name));
+        }
+        let start = self.next.fetch_add(1, Ordering::SeqCst) % self.accounts.len();
+        let best = (0..self.accounts.len())
+            .map(|i| &self.accounts[(start + i) % self.accounts.len()])
+            .filter(|a| a.is_available())
+            .min_by_key(|a| a.in_flight());
+        match best {
+            Some(account) => Ok(AccountLease::new(account)),
+            None => Err(anyhow!("没有可用的 {} 账户", self.name)),
+        }
+    }
+
+    // 指定账户 (例如继续 poll 已有提交), 不检查健康状态
+    pub fn lease(&'static self, username: &str) -> anyhow::Result<AccountLease> {
+        self.accounts
+            .iter()
+            .find(|a| a.handler.username == username)
+            .map(AccountLease::new)
+            .ok_or_else(|| anyhow!("未找到 {} 账户 {}", self.name, username))
+    }
+}
+
+pub fn account_pool(remote_judge_name: &str) -> anyhow::Result<&'static AccountPool> {
+    static POOLS: OnceCell<HashMap<&'static str, AccountPool>> = OnceCell::new();
+    let pools = POOLS.get_or_init(|| {
+        [
+            (constant::names::CODEFORCES, constant::base_url::CODEFORCES),
+            (constant::names::HDU, constant::base_url::HDU),
+            (constant::names::ATCODER, constant::base_url::ATCODER),
+        ]
+        .into_iter()
+        .map(|(name, url)| (name, AccountPool::new(name, url)))
+        .collect()
+    });
+    // gym 与 codeforces 共用账户
+    let name = if remote_judge_name == constant::names::GYM {
+        constant::names::CODEFORCES
+    } else {
+        remote_judge_name
+    };
+    pools
+        .get(name)
+        .ok_or_else(|| anyhow!("不支持 {} 账户池", remote_judge_name))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_check_banned() {
+        let account = PooledAccount::new(
+            Handler::new("a".into(), "p".into(), constant::base_url::CODEFORCES),
+            false,
+            vec!["is blocked".into()],
+            notice_selector(constant::names::CODEFORCES),
+        );
+        let html = r#"<div class="problem-statement">The road is blocked by a tree.</div>
+            <span class="error for__password">Invalid handle/email or password</span>"#;
+        assert!(account.check_banned(html).is_ok());
+        assert!(account.is_available());
+
+        let html = r#"<span class="error for__password">User a Is Blocked</span>"#;
+        assert!(account.check_banned(html).is_err());
+        assert_eq!(account.state(), AccountState::CoolingDown);
+        account.report_login(false);
+        assert_eq!(account.state(), AccountState::CoolingDown);
+        account.report_login(true);
+        assert_eq!(account.state(), AccountState::LoggedIn);
+    }
+}
@@ -1,7 +1,10 @@
+use super::Verdict;
+use crate::global::remote_judge_constant::names;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 pub struct Problem {
     pub problem_id: String,
     pub title: String,
@@ -16,15 +19,127 @@ pub struct Problem {
     pub others: HashMap<String, String>,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 pub struct SubmissionStatus {
     pub submission_id: String,
-    pub status: String,
+    pub status: Verdict,
     pub info: String,
     pub is_over: bool,
     pub score: u16,
     pub time: u32,   //MS
     pub memory: u32, //KB
-    pub compile: Option<serde_json::Value>,
-    pub judge: Option<serde_json::Value>,
+    pub compile: Option<CompileResult>,
+    pub judge: Option<JudgeDetail>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct CompileResult {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct JudgeDetail {
+    pub subtasks: Vec<Subtask>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct Subtask {
+    pub score: u16,
+    pub cases: Vec<CaseResult>,
+}
+
+// 测试点状态, 与 syzoj 的 TaskStatus 对应, 远程 oj 只返回已完成的测试点
+pub const CASE_DONE: u8 = 2;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct CaseResult {
+    pub status: u8,
+    pub result: CaseDetail,
+}
+
+impl CaseResult {
+    pub fn done(result: CaseDetail) -> Self {
+        Self {
+            status: CASE_DONE,
+            result,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CaseDetail {
+    #[serde(rename = "type")]
+    pub verdict: Verdict,
+    pub scoring_rate: f64, // 0 ~ 1
+    pub time: u32,         // MS
+    pub memory: u32,       // KB
+    pub input: Option<CaseFile>,
+    pub output: Option<CaseFile>,
+    pub user_output: Option<String>,
+    pub spj_message: Option<String>,
+}
+
+impl CaseDetail {
+    pub fn new(verdict: Verdict, time: u32, memory: u32) -> Self {
+        Self {
+            scoring_rate: if verdict == Verdict::Accepted { 1.0 } else { 0.0 },
+            verdict,
+            time,
+            memory,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct CaseFile {
+    pub name: String,
+    pub content: String,
+}
+
+// 远程提交的句柄, 包含重新 poll 该提交所需的全部信息, 可序列化后交给客户端保存
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SubmissionHandle {
+    pub oj: String,
+    pub account: String,
+    pub contest_id: Option<String>,
+    pub problem_id: String,
+    pub remote_id: String,
+    #[serde(default)]
+    pub marker: Option<String>, // 提交时注入的水印
+}
+
+impl SubmissionHandle {
+    // 只知道远程 submission id 时, 按各 oj 的题号规则补全比赛信息
+    pub fn from_remote_id(oj: &str, account: &str, problem_id: &str, remote_id: &str) -> Self {
+        let contest_id = match oj {
+            names::CODEFORCES | names::GYM => {
+                let pos = problem_id.find(|c: char| c.is_alphabetic()).unwrap_or(0);
+                Some(problem_id[..pos].to_string())
+            }
+            names::ATCODER => {
+                let pos = problem_id.find('_').unwrap_or(0);
+                Some(problem_id[..pos].to_string())
+            }
+            _ => None,
+        };
+        Self {
+            oj: oj.into(),
+            account: account.into(),
+            contest_id,
+            problem_id: problem_id.into(),
+            remote_id: remote_id.into(),
+            marker: None,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        base64_url::encode(&serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(token: &str) -> anyhow::Result<Self> {
+        let bytes = base64_url::decode(token)?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
 }
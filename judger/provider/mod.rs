@@ -1,4 +1,11 @@
 mod definition;
 mod provider;
-pub use definition::{Problem, SubmissionStatus};
+mod verdict;
+mod verdict_rule;
+pub use definition::{
+    CaseDetail, CaseFile, CaseResult, CompileResult, JudgeDetail, Problem, SubmissionHandle,
+    SubmissionStatus, Subtask, CASE_DONE,
+};
 pub use provider::Provider;
+pub use verdict::Verdict;
+pub use verdict_rule::{MatchKind, VerdictRule, VerdictRules};
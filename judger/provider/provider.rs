@@ -1,4 +1,5 @@
-use super::{Problem, SubmissionStatus};
+use super::{Problem, SubmissionHandle, SubmissionStatus};
+use std::collections::HashMap;
 
 pub trait Provider: Send {
     async fn get_problem(&self, __problem_id: &str) -> anyhow::Result<Problem>;
@@ -8,7 +9,22 @@ pub trait Provider: Send {
         __problem_id: &str,
         __source: &str,
         __lang: &str,
-    ) -> anyhow::Result<(String, serde_json::Value)>; // 返回 submission id 和 返回服务方其他信息
+    ) -> anyhow::Result<SubmissionHandle>;
 
-    async fn poll(&self, submission_id: &str) -> anyhow::Result<SubmissionStatus>;
+    async fn poll(&self, handle: &SubmissionHandle) -> anyhow::Result<SubmissionStatus>;
+
+    // 更新同一账户的多个提交, 返回 remote_id -> 状态; 获取失败的提交不在结果中
+    // 默认逐个 poll, 各 oj 用列表页一次请求获取全部状态, 测评结束后才获取详细的测试点信息
+    async fn poll_many(
+        &self,
+        handles: &[SubmissionHandle],
+    ) -> anyhow::Result<HashMap<String, SubmissionStatus>> {
+        let mut res = HashMap::new();
+        for handle in handles {
+            if let Ok(status) = self.poll(handle).await {
+                res.insert(handle.remote_id.clone(), status);
+            }
+        }
+        Ok(res)
+    }
 }
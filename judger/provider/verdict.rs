@@ -0,0 +1,123 @@
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// 测评结果, 序列化为与之前版本相同的字符串
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Verdict {
+    Accepted,
+    WrongAnswer,
+    PresentationError,
+    RuntimeError,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    OutputLimitExceeded,
+    IdlenessLimitExceeded,
+    CompileError,
+    SystemError,
+    Skipped,
+    Hacked,
+    Partial,
+    Running,
+    #[default]
+    Waiting,
+    Unknown(String), // 无法识别的原始结果
+}
+
+impl Verdict {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Verdict::Accepted => "Accepted",
+            Verdict::WrongAnswer => "Wrong Answer",
+            Verdict::PresentationError => "Presentation Error",
+            Verdict::RuntimeError => "Runtime Error",
+            Verdict::TimeLimitExceeded => "Time Limit Exceeded",
+            Verdict::MemoryLimitExceeded => "Memory Limit Exceeded",
+            Verdict::OutputLimitExceeded => "Output Limit Exceeded",
+            Verdict::IdlenessLimitExceeded => "Idleness Limit Exceeded",
+            Verdict::CompileError => "Compile Error",
+            Verdict::SystemError => "System Error",
+            Verdict::Skipped => "Skipped",
+            Verdict::Hacked => "Hacked",
+            Verdict::Partial => "Partial",
+            Verdict::Running => "Running",
+            Verdict::Waiting => "Waiting",
+            Verdict::Unknown(raw) => raw.as_str(),
+        }
+    }
+
+    pub fn from_wire(s: &str) -> Self {
+        match s {
+            "Accepted" => Verdict::Accepted,
+            "Wrong Answer" => Verdict::WrongAnswer,
+            "Presentation Error" => Verdict::PresentationError,
+            "Runtime Error" => Verdict::RuntimeError,
+            "Time Limit Exceeded" => Verdict::TimeLimitExceeded,
+            "Memory Limit Exceeded" => Verdict::MemoryLimitExceeded,
+            "Output Limit Exceeded" => Verdict::OutputLimitExceeded,
+            "Idleness Limit Exceeded" => Verdict::IdlenessLimitExceeded,
+            "Compile Error" => Verdict::CompileError,
+            "System Error" | "Internal Error" => Verdict::SystemError,
+            "Skipped" => Verdict::Skipped,
+            "Hacked" => Verdict::Hacked,
+            "Partial" => Verdict::Partial,
+            "Running" => Verdict::Running,
+            "Waiting" => Verdict::Waiting,
+            _ => Verdict::Unknown(s.into()),
+        }
+    }
+
+    // 仍在排队或测评中; 无法识别的结果按最终结果处理
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Verdict::Waiting | Verdict::Running)
+    }
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Verdict {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Verdict {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(Verdict::from_wire(&s))
+    }
+}
+
+// 线上格式是字符串, 无法识别的结果原样保留, 因此不限定取值
+impl JsonSchema for Verdict {
+    fn schema_name() -> String {
+        "Verdict".into()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_wire() {
+        assert_eq!(
+            serde_json::to_string(&Verdict::CompileError).unwrap(),
+            r#""Compile Error""#
+        );
+        let v: Verdict = serde_json::from_str(r#""Time Limit Exceeded""#).unwrap();
+        assert_eq!(v, Verdict::TimeLimitExceeded);
+        let v: Verdict = serde_json::from_str(r#""Judgement Failed""#).unwrap();
+        assert_eq!(v, Verdict::Unknown("Judgement Failed".into()));
+        assert_eq!(v.to_string(), "Judgement Failed");
+        assert!(!v.is_pending());
+    }
+}
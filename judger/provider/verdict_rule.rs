@@ -0,0 +1,152 @@
+// 远程 oj 原始结果文本到 Verdict 的映射规则, 按顺序匹配, 第一条命中的规则生效
+use super::Verdict;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    Exact,
+    Prefix,
+    Contains,
+    Regex,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct RawRule {
+    #[serde(rename = "match")]
+    kind: MatchKind,
+    pattern: String,
+    verdict: Verdict,
+}
+
+// remote_judge_config.json 中的写法:
+// { "match": "prefix", "pattern": "Wrong answer", "verdict": "Wrong Answer" }
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RawRule", into = "RawRule")]
+pub struct VerdictRule {
+    pub kind: MatchKind,
+    pub pattern: String,
+    regex: Option<regex::Regex>,
+    pub verdict: Verdict,
+}
+
+impl TryFrom<RawRule> for VerdictRule {
+    type Error = regex::Error;
+
+    fn try_from(raw: RawRule) -> Result<Self, Self::Error> {
+        let regex = match raw.kind {
+            MatchKind::Regex => Some(regex::Regex::new(&raw.pattern)?),
+            _ => None,
+        };
+        Ok(Self {
+            kind: raw.kind,
+            pattern: raw.pattern,
+            regex,
+            verdict: raw.verdict,
+        })
+    }
+}
+
+impl From<VerdictRule> for RawRule {
+    fn from(rule: VerdictRule) -> Self {
+        Self {
+            kind: rule.kind,
+            pattern: rule.pattern,
+            verdict: rule.verdict,
+        }
+    }
+}
+
+impl VerdictRule {
+    // 默认规则都是写死的字符串, 正则写错直接 panic
+    pub fn new(kind: MatchKind, pattern: &str, verdict: Verdict) -> Self {
+        Self::try_from(RawRule {
+            kind,
+            pattern: pattern.into(),
+            verdict,
+        })
+        .expect("默认测评结果规则有误")
+    }
+
+    pub fn exact(pattern: &str, verdict: Verdict) -> Self {
+        Self::new(MatchKind::Exact, pattern, verdict)
+    }
+
+    pub fn prefix(pattern: &str, verdict: Verdict) -> Self {
+        Self::new(MatchKind::Prefix, pattern, verdict)
+    }
+
+    pub fn contains(pattern: &str, verdict: Verdict) -> Self {
+        Self::new(MatchKind::Contains, pattern, verdict)
+    }
+
+    pub fn regex(pattern: &str, verdict: Verdict) -> Self {
+        Self::new(MatchKind::Regex, pattern, verdict)
+    }
+
+    pub fn matches(&self, raw: &str) -> bool {
+        match self.kind {
+            MatchKind::Exact => raw == self.pattern,
+            MatchKind::Prefix => raw.starts_with(self.pattern.as_str()),
+            MatchKind::Contains => raw.contains(self.pattern.as_str()),
+            MatchKind::Regex => self.regex.as_ref().map_or(false, |re| re.is_match(raw)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VerdictRules {
+    rules: Vec<VerdictRule>,
+}
+
+impl VerdictRules {
+    // 配置中的规则排在默认规则之前, 可以覆盖默认映射
+    pub fn new(configured: &[VerdictRule], defaults: Vec<VerdictRule>) -> Self {
+        Self {
+            rules: configured.iter().cloned().chain(defaults).collect(),
+        }
+    }
+
+    pub fn resolve(&self, raw: &str) -> Verdict {
+        let raw = raw.trim();
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(raw))
+            .map(|rule| rule.verdict.clone())
+            .unwrap_or_else(|| Verdict::Unknown(raw.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let configured: Vec<VerdictRule> = serde_json::from_str(
+            r#"[{ "match": "regex", "pattern": "^\\d+/\\d+", "verdict": "Running" }]"#,
+        )
+        .unwrap();
+        let rules = VerdictRules::new(
+            &configured,
+            vec![
+                VerdictRule::exact("AC", Verdict::Accepted),
+                VerdictRule::prefix("Wrong answer", Verdict::WrongAnswer),
+                VerdictRule::contains("answer", Verdict::Partial),
+            ],
+        );
+        assert_eq!(rules.resolve("AC"), Verdict::Accepted);
+        assert_eq!(rules.resolve("3/20 WA"), Verdict::Running);
+        assert_eq!(
+            rules.resolve("Wrong answer on test 3"),
+            Verdict::WrongAnswer
+        );
+        assert_eq!(rules.resolve("Partial answer"), Verdict::Partial);
+        assert_eq!(rules.resolve("ACC"), Verdict::Unknown("ACC".into()));
+
+        let bad = r#"[{ "match": "regex", "pattern": "(", "verdict": "Running" }]"#;
+        assert!(serde_json::from_str::<Vec<VerdictRule>>(bad).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+// 访问远程 oj 的熔断: 被拦截, 返回 429/503 或维护页面时立即熔断, 连续 failure_threshold 次网络错误或 5xx 也会熔断
+// 熔断期间的请求直接失败; 冷却时间过后放行一个探测请求, 成功则恢复, 失败则冷却时间翻倍, 最长 max_open_secs
+// gym 与 codeforces 共用账户, 也共用熔断器
+use super::now_timestamp;
+use crate::global::remote_judge_constant::names as remote_judge_names;
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use simple_log::{error, info};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+// 熔断期间返回给客户端的错误码
+pub const CIRCUIT_OPEN: &str = "circuit_open";
+// 探测请求被取消时不会有结果, 超过该时间后放行新的探测请求
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BreakerConfig {
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    #[serde(default = "default_open_secs")]
+    pub open_secs: u64, // 第一次熔断的冷却时间, 秒
+    #[serde(default = "default_max_open_secs")]
+    pub max_open_secs: u64,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_failure_threshold(),
+            open_secs: default_open_secs(),
+            max_open_secs: default_max_open_secs(),
+        }
+    }
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_open_secs() -> u64 {
+    30
+}
+
+fn default_max_open_secs() -> u64 {
+    600
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen, // 探测请求进行中
+}
+
+// 熔断期间的请求返回的错误, 可通过 downcast 区分
+#[derive(Debug, Clone)]
+pub struct CircuitOpen {
+    pub oj: String,
+    pub retry_after: u64, // 秒
+}
+
+impl std::fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} 暂时不可用 (已熔断), 请 {} 秒后重试",
+            self.oj, self.retry_after
+        )
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+// GET /breakers 返回的熔断器状态
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerView {
+    pub oj: String,
+    pub state: BreakerState,
+    pub failures: u32,
+    pub reason: Option<String>,
+    pub opened_at: Option<i64>,
+    pub retry_after: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: BreakerState,
+    failures: u32,
+    backoff: Duration, // 上一次的冷却时间, 恢复后再次成功才清零
+    until: Instant,
+    reason: Option<String>,
+    opened_at: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    oj: &'static str,
+    config: BreakerConfig,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    fn new(oj: &'static str, config: &BreakerConfig) -> Self {
+        Self {
+            oj,
+            config: config.clone(),
+            inner: Mutex::new(Inner {
+                state: BreakerState::Closed,
+                failures: 0,
+                backoff: Duration::ZERO,
+                until: Instant::now(),
+                reason: None,
+                opened_at: None,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn open_error(&self, inner: &Inner) -> CircuitOpen {
+        let remaining = inner.until.saturating_duration_since(Instant::now());
+        CircuitOpen {
+            oj: self.oj.to_string(),
+            retry_after: (remaining.as_secs_f64().ceil() as u64).max(1),
+        }
+    }
+
+    fn open(&self, inner: &mut Inner, reason: String, retry_after: Option<Duration>) {
+        let base = Duration::from_secs(self.config.open_secs);
+        let max = Duration::from_secs(self.config.max_open_secs.max(self.config.open_secs));
+        inner.backoff = if inner.backoff.is_zero() {
+            base
+        } else {
+            std::cmp::min(inner.backoff * 2, max)
+        };
+        let wait = std::cmp::max(inner.backoff, retry_after.unwrap_or_default());
+        inner.state = BreakerState::Open;
+        inner.until = Instant::now() + wait;
+        inner.opened_at = Some(now_timestamp());
+        error!("{} 已熔断 {} 秒: {}", self.oj, wait.as_secs(), reason);
+        inner.reason = Some(reason);
+    }
+
+    // 不改变状态, 熔断中返回错误; 冷却时间已过时返回 Ok, 之后的请求作为探测
+    pub fn check(&self) -> Result<(), CircuitOpen> {
+        let inner = self.lock();
+        match inner.state {
+            BreakerState::Closed => Ok(()),
+            _ if inner.until <= Instant::now() => Ok(()),
+            _ => Err(self.open_error(&inner)),
+        }
+    }
+
+    // 发出请求前调用, 冷却时间已过时只放行一个探测请求
+    pub fn before(&self) -> Result<(), CircuitOpen> {
+        let mut inner = self.lock();
+        match inner.state {
+            BreakerState::Closed => Ok(()),
+            _ if inner.until <= Instant::now() => {
+                info!("{} 熔断冷却结束, 发送探测请求", self.oj);
+                inner.state = BreakerState::HalfOpen;
+                inner.until = Instant::now() + PROBE_TIMEOUT;
+                Ok(())
+            }
+            _ => Err(self.open_error(&inner)),
+        }
+    }
+
+    pub fn success(&self) {
+        let mut inner = self.lock();
+        match inner.state {
+            BreakerState::HalfOpen => {
+                info!("{} 探测成功, 恢复访问", self.oj);
+                inner.state = BreakerState::Closed;
+                inner.failures = 0;
+                inner.reason = None;
+                inner.opened_at = None;
+            }
+            BreakerState::Closed => {
+                inner.failures = 0;
+                inner.backoff = Duration::ZERO;
+            }
+            BreakerState::Open => {}
+        }
+    }
+
+    // 网络错误或 5xx, 可能只是偶发的失败
+    pub fn failure(&self, reason: impl std::fmt::Display) {
+        let mut inner = self.lock();
+        match inner.state {
+            BreakerState::Closed => {
+                inner.failures += 1;
+                if inner.failures >= self.config.failure_threshold.max(1) {
+                    let reason = format!("连续 {} 次请求失败: {}", inner.failures, reason);
+                    self.open(&mut inner, reason, None);
+                }
+            }
+            BreakerState::HalfOpen => self.open(&mut inner, reason.to_string(), None),
+            BreakerState::Open => {}
+        }
+    }
+
+    // 被拦截, 限流或维护中, 立即熔断; 返回之后请求会得到的错误
+    pub fn trip(
+        &self,
+        reason: impl std::fmt::Display,
+        retry_after: Option<Duration>,
+    ) -> CircuitOpen {
+        let mut inner = self.lock();
+        match inner.state {
+            BreakerState::Open => {
+                if let Some(retry_after) = retry_after {
+                    inner.until = std::cmp::max(inner.until, Instant::now() + retry_after);
+                }
+            }
+            _ => self.open(&mut inner, reason.to_string(), retry_after),
+        }
+        self.open_error(&inner)
+    }
+
+    pub fn view(&self) -> BreakerView {
+        let inner = self.lock();
+        let remaining = inner.until.saturating_duration_since(Instant::now());
+        BreakerView {
+            oj: self.oj.to_string(),
+            state: inner.state,
+            failures: inner.failures,
+            reason: inner.reason.clone(),
+            opened_at: inner.opened_at,
+            retry_after: (inner.state != BreakerState::Closed).then(|| remaining.as_secs()),
+        }
+    }
+}
+
+type Registry = Mutex<BTreeMap<&'static str, Arc<CircuitBreaker>>>;
+
+fn registry() -> &'static Registry {
+    static BREAKERS: OnceCell<Registry> = OnceCell::new();
+    BREAKERS.get_or_init(Default::default)
+}
+
+fn name(oj: &str) -> &str {
+    if oj == remote_judge_names::GYM {
+        remote_judge_names::CODEFORCES
+    } else {
+        oj
+    }
+}
+
+// 账户池创建时为每个 oj 创建一次
+pub fn breaker(oj: &'static str, config: &BreakerConfig) -> Arc<CircuitBreaker> {
+    registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(oj)
+        .or_insert_with(|| Arc::new(CircuitBreaker::new(oj, config)))
+        .clone()
+}
+
+// 新任务开始前检查, 还没有访问过的 oj 视为正常
+pub fn check(oj: &str) -> Result<(), CircuitOpen> {
+    let breaker = registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name(oj))
+        .cloned();
+    breaker.map_or(Ok(()), |b| b.check())
+}
+
+// 请求成功但结果不可用 (例如页面无法解析) 时由调用方计入失败
+pub fn failure(oj: &str, reason: impl std::fmt::Display) {
+    let breaker = registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name(oj))
+        .cloned();
+    if let Some(breaker) = breaker {
+        breaker.failure(reason);
+    }
+}
+
+pub fn views() -> Vec<BreakerView> {
+    registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .map(|b| b.view())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_breaker() {
+        let breaker = CircuitBreaker::new(
+            "hdu",
+            &BreakerConfig {
+                failure_threshold: 2,
+                open_secs: 0,
+                max_open_secs: 0,
+            },
+        );
+        breaker.failure("timeout");
+        assert!(breaker.before().is_ok());
+        breaker.failure("timeout");
+        assert_eq!(breaker.view().state, BreakerState::Open);
+
+        // 冷却时间为 0, 只放行一个探测请求
+        assert!(breaker.before().is_ok());
+        assert_eq!(breaker.view().state, BreakerState::HalfOpen);
+        assert!(breaker.before().is_err());
+        breaker.success();
+        assert_eq!(breaker.view().state, BreakerState::Closed);
+
+        let e = breaker.trip("http 429", Some(Duration::from_secs(60)));
+        assert_eq!(e.retry_after, 60);
+        assert!(breaker.check().is_err());
+    }
+}
@@ -1,3 +1,6 @@
+pub mod breaker;
+pub mod rate_limit;
 pub mod request;
+pub mod session;
 mod utils;
 pub use utils::*;
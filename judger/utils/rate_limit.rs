@@ -0,0 +1,232 @@
+// 访问远程 oj 的令牌桶限流: 同一 host 的所有账户共用一个桶, 每个账户另有一个桶
+// 令牌不足时按到达顺序预约令牌并等待, 等待时间超过 timeout 的请求直接失败
+use anyhow::anyhow;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Bucket {
+    pub rate: f64,  // 每秒补充的令牌数
+    pub burst: u32, // 桶容量
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    pub host: Option<Bucket>,
+    pub account: Option<Bucket>,
+    #[serde(default = "default_timeout")]
+    pub timeout: u64, // 最长排队时间, 秒
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            account: None,
+            timeout: default_timeout(),
+        }
+    }
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    requests: AtomicU64,
+    waits: AtomicU64,
+    wait_micros: AtomicU64,
+    rejected: AtomicU64,
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    state: Mutex<(f64, Instant)>, // (剩余令牌, 上次补充时间), 令牌为负表示已被预约
+    metrics: Metrics,
+}
+
+impl TokenBucket {
+    fn new(bucket: &Bucket) -> Self {
+        let burst = bucket.burst.max(1) as f64;
+        Self {
+            rate: bucket.rate.max(0.001),
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+            metrics: Metrics::default(),
+        }
+    }
+
+    // 预约一个令牌, 返回需要等待的时间
+    fn reserve(&self, timeout: Duration) -> anyhow::Result<Duration> {
+        self.metrics.requests.fetch_add(1, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let tokens = (state.0 + (now - state.1).as_secs_f64() * self.rate).min(self.burst);
+        let wait = Duration::from_secs_f64(((1.0 - tokens) / self.rate).max(0.0));
+        if wait > timeout {
+            self.metrics.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(anyhow!("请求过于频繁, 等待超时"));
+        }
+        *state = (tokens - 1.0, now);
+        Ok(wait)
+    }
+
+    // 其他桶拒绝时归还已预约的令牌
+    fn refund(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.0 = (state.0 + 1.0).min(self.burst);
+    }
+
+    fn record_wait(&self, wait: Duration) {
+        if !wait.is_zero() {
+            self.metrics.waits.fetch_add(1, Ordering::Relaxed);
+            self.metrics
+                .wait_micros
+                .fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+type Registry = Mutex<BTreeMap<(&'static str, String), Arc<TokenBucket>>>;
+
+fn registry() -> &'static Registry {
+    static BUCKETS: OnceCell<Registry> = OnceCell::new();
+    BUCKETS.get_or_init(Default::default)
+}
+
+// 同名的桶只创建一次, 例如 gym 与 codeforces 的账户共用 codeforces.com 的 host 桶时以先创建的配置为准
+fn bucket(kind: &'static str, key: &str, config: &Bucket) -> Arc<TokenBucket> {
+    registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry((kind, key.to_string()))
+        .or_insert_with(|| Arc::new(TokenBucket::new(config)))
+        .clone()
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Vec<Arc<TokenBucket>>,
+    timeout: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(host: &str, account: &str, config: &RateLimitConfig) -> Self {
+        let mut buckets = vec![];
+        if let Some(b) = config.host.as_ref() {
+            buckets.push(bucket("host", host, b));
+        }
+        if let Some(b) = config.account.as_ref() {
+            buckets.push(bucket("account", &format!("{}/{}", host, account), b));
+        }
+        Self {
+            buckets,
+            timeout: Duration::from_secs(config.timeout),
+        }
+    }
+
+    // 依次从各个桶预约令牌, 等待其中最长的时间; 任一个桶拒绝时归还之前预约的令牌
+    pub async fn acquire(&self) -> anyhow::Result<()> {
+        let mut waits = Vec::with_capacity(self.buckets.len());
+        for bucket in self.buckets.iter() {
+            match bucket.reserve(self.timeout) {
+                Ok(wait) => waits.push(wait),
+                Err(e) => {
+                    self.buckets[..waits.len()].iter().for_each(|b| b.refund());
+                    return Err(e);
+                }
+            }
+        }
+        let mut wait = Duration::ZERO;
+        for (bucket, w) in self.buckets.iter().zip(waits) {
+            bucket.record_wait(w);
+            wait = wait.max(w);
+        }
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+}
+
+// prometheus 文本格式的限流统计
+pub fn metrics() -> String {
+    let buckets = registry().lock().unwrap_or_else(|e| e.into_inner());
+    let mut out = String::new();
+    let mut metric = |name: &str, help: &str, value: &dyn Fn(&Metrics) -> String| {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} counter\n",
+            name, help, name
+        ));
+        for ((kind, key), bucket) in buckets.iter() {
+            out.push_str(&format!(
+                "{}{{bucket=\"{}\",key=\"{}\"}} {}\n",
+                name,
+                kind,
+                key,
+                value(&bucket.metrics)
+            ));
+        }
+    };
+    metric(
+        "remote_judge_rate_limit_requests_total",
+        "Requests that passed through the rate limiter",
+        &|m| m.requests.load(Ordering::Relaxed).to_string(),
+    );
+    metric(
+        "remote_judge_rate_limit_waits_total",
+        "Requests that had to wait for a token",
+        &|m| m.waits.load(Ordering::Relaxed).to_string(),
+    );
+    metric(
+        "remote_judge_rate_limit_wait_seconds_total",
+        "Total time spent waiting for tokens",
+        &|m| format!("{:.3}", m.wait_micros.load(Ordering::Relaxed) as f64 / 1e6),
+    );
+    metric(
+        "remote_judge_rate_limit_rejected_total",
+        "Requests rejected because the wait exceeded the timeout",
+        &|m| m.rejected.load(Ordering::Relaxed).to_string(),
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reserve() {
+        let bucket = TokenBucket::new(&Bucket {
+            rate: 1.0,
+            burst: 2,
+        });
+        let timeout = Duration::from_secs(1);
+        assert!(bucket.reserve(timeout).unwrap().is_zero());
+        assert!(bucket.reserve(timeout).unwrap().is_zero());
+        assert!(bucket.reserve(timeout).unwrap() > Duration::from_millis(900));
+        assert!(bucket.reserve(timeout).is_err());
+        assert_eq!(bucket.metrics.rejected.load(Ordering::Relaxed), 1);
+
+        // 账户的桶拒绝时, host 的桶归还令牌
+        let host = Arc::new(TokenBucket::new(&Bucket {
+            rate: 1.0,
+            burst: 1,
+        }));
+        let limiter = RateLimiter {
+            buckets: vec![host.clone(), Arc::new(bucket)],
+            timeout,
+        };
+        assert!(limiter.acquire().await.is_err());
+        assert!(host.reserve(timeout).unwrap().is_zero());
+    }
+}
@@ -1,7 +1,12 @@
 use std::collections::HashMap;
-
-use anyhow::Result;
-use reqwest::{header, Client};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::breaker::CircuitBreaker;
+use super::rate_limit::RateLimiter;
+use anyhow::{anyhow, Result};
+use cookie_store::{Cookie, RawCookie};
+use reqwest::{header, Client, RequestBuilder, StatusCode, Url};
 use serde::Serialize;
 
 pub struct PostConfig {
@@ -27,23 +32,139 @@ fn default_header() -> header::HeaderMap {
     headers
 }
 
+// 与 reqwest 自带的 Jar 相同, 但保留 cookie 的 domain / path / 过期时间等属性, 便于持久化
+#[derive(Debug, Default)]
+struct CookieJar(RwLock<cookie_store::CookieStore>);
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(
+        &self,
+        cookie_headers: &mut dyn Iterator<Item = &header::HeaderValue>,
+        url: &Url,
+    ) {
+        let cookies = cookie_headers
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|c| RawCookie::parse(c.to_string()).ok());
+        self.0
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<header::HeaderValue> {
+        let store = self.0.read().unwrap_or_else(|e| e.into_inner());
+        let cookies = store
+            .get_request_values(url)
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if cookies.is_empty() {
+            return None;
+        }
+        header::HeaderValue::from_str(&cookies).ok()
+    }
+}
+
 #[derive(Debug)]
 pub struct RemoteJudgeRequest {
     pub client: Client,
     pub base_url: &'static str,
+    jar: Arc<CookieJar>,
+    limiter: RateLimiter,
+    breaker: Option<Arc<CircuitBreaker>>,
 }
 
 impl RemoteJudgeRequest {
     pub fn new(base_url: &'static str) -> Self {
+        let jar = Arc::new(CookieJar::default());
         Self {
             client:  Client::builder()
             .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/108.0.0.0 Safari/537.36 Edg/108.0.1462.15")
             .timeout(std::time::Duration::from_secs(10))
-            .cookie_store(true)
+            .cookie_provider(jar.clone())
             .default_headers(default_header())
             .build()
             .expect("创建 client 失败"),
-            base_url
+            base_url,
+            jar,
+            limiter: RateLimiter::default(),
+            breaker: None,
+        }
+    }
+
+    // get / post 都要先通过限流
+    pub fn set_rate_limiter(&mut self, limiter: RateLimiter) {
+        self.limiter = limiter;
+    }
+
+    pub fn set_breaker(&mut self, breaker: Arc<CircuitBreaker>) {
+        self.breaker = Some(breaker);
+    }
+
+    // 页面显示访问被拦截或维护中时调用, 之后的请求在冷却时间内直接失败
+    pub fn block(&self, reason: impl std::fmt::Display) -> anyhow::Error {
+        match self.breaker.as_ref() {
+            Some(breaker) => breaker.trip(reason, None).into(),
+            None => anyhow!("{}", reason),
+        }
+    }
+
+    // 请求结果反馈给熔断器: 429/503 立即熔断, 网络错误与其他 5xx 累计失败次数
+    // 熔断中的请求不占用限流的令牌
+    async fn send(&self, req: RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let Some(breaker) = self.breaker.as_ref() else {
+            self.limiter.acquire().await?;
+            return Ok(req.send().await?);
+        };
+        breaker.check()?;
+        self.limiter.acquire().await?;
+        breaker.before()?;
+        let resp = match req.send().await {
+            Result::Ok(resp) => resp,
+            Err(e) => {
+                breaker.failure(&e);
+                return Err(e.into());
+            }
+        };
+        let status = resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = resp
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs);
+            return Err(breaker.trip(format!("http {}", status), retry_after).into());
+        }
+        if status.is_server_error() {
+            breaker.failure(format!("http {}", status));
+        } else {
+            breaker.success();
+        }
+        Ok(resp)
+    }
+
+    pub fn host(&self) -> &str {
+        self.base_url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_end_matches('/')
+    }
+
+    // 导出全部未过期的 cookie, 包括 domain / path / 过期时间等属性
+    pub fn export_cookies(&self) -> Vec<Cookie<'static>> {
+        let store = self.jar.0.read().unwrap_or_else(|e| e.into_inner());
+        store.iter_unexpired().cloned().collect()
+    }
+
+    // 没有过期时间的会话 cookie 由调用方按会话有效期决定是否导入
+    pub fn import_cookies(&self, cookies: Vec<Cookie<'static>>) {
+        let Result::Ok(url) = Url::parse(self.base_url) else {
+            return;
+        };
+        let mut store = self.jar.0.write().unwrap_or_else(|e| e.into_inner());
+        for cookie in cookies {
+            let _ = store.insert(cookie, &url);
         }
     }
 
@@ -67,28 +188,17 @@ impl RemoteJudgeRequest {
     }
 
     pub async fn get(&self, url: &str) -> anyhow::Result<reqwest::Response> {
-        Ok(self.client.get(self.get_url(url)).send().await?)
+        self.send(self.client.get(self.get_url(url))).await
     }
 
     pub fn get_cookie_kv(&self) -> HashMap<String, String> {
-        let Result::Ok(q) = self.client.get(self.base_url).build() else {
-            return HashMap::default();
-        };
-
-        println!("{:?}", q.headers());
-
-        let Some(cookie_header) = q.headers().get("cookie") else {
+        let Result::Ok(url) = Url::parse(self.base_url) else {
             return HashMap::default();
         };
-
-        cookie_header
-            .to_str()
-            .unwrap_or_default()
-            .split("; ")
-            .map(|c| {
-                let pos = c.find("=").unwrap_or(0);
-                (c[..pos].to_string(), c[pos + 1..].to_string())
-            })
+        let store = self.jar.0.read().unwrap_or_else(|e| e.into_inner());
+        store
+            .get_request_values(&url)
+            .map(|(k, v)| (k.to_string(), v.to_string()))
             .collect()
     }
 
@@ -97,12 +207,8 @@ impl RemoteJudgeRequest {
         url: &str,
         data: &T,
     ) -> anyhow::Result<reqwest::Response> {
-        Ok(self
-            .client
-            .post(self.get_url(url))
-            .form(data)
-            .send()
-            .await?)
+        self.send(self.client.post(self.get_url(url)).form(data))
+            .await
     }
 
     pub async fn post_with_config<T: Serialize + ?Sized>(
@@ -119,6 +225,6 @@ impl RemoteJudgeRequest {
         if let Some(t) = config.timeout {
             req = req.timeout(t);
         }
-        Ok(req.send().await?)
+        self.send(req).await
     }
 }
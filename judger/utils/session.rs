@@ -0,0 +1,110 @@
+// 账户会话 (cookie) 持久化, 重启后复用登录状态, 避免集中重新登录被远程 oj 限制
+use crate::global::server_config;
+
+use cookie_store::Cookie;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct StoredSession {
+    pub cookies: Vec<Cookie<'static>>, // 保留 domain / path / 过期时间等属性
+    pub saved_at: i64,                 // unix 时间戳, 秒
+}
+
+impl StoredSession {
+    // 带过期时间的 cookie 按各自的过期时间失效, 没有过期时间的会话 cookie 在保存 ttl 秒后失效
+    pub fn into_cookies(self, ttl: u64) -> Vec<Cookie<'static>> {
+        let expired = self.saved_at + ttl as i64 <= super::now_timestamp();
+        self.cookies
+            .into_iter()
+            .filter(|c| !c.is_expired() && (c.is_persistent() || !expired))
+            .collect()
+    }
+}
+
+pub trait SessionStore: Send + Sync {
+    fn load(&self, key: &str) -> Option<StoredSession>;
+    fn save(&self, key: &str, session: &StoredSession) -> anyhow::Result<()>;
+    fn remove(&self, key: &str);
+}
+
+// 每个账户一个 json 文件
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let name: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self, key: &str) -> Option<StoredSession> {
+        let data = std::fs::read(self.path(key)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    fn save(&self, key: &str, session: &StoredSession) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(key);
+        std::fs::write(&path, serde_json::to_vec(session)?)?;
+        // 会话文件等同于登录凭据, 只允许本用户读写
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = std::fs::remove_file(self.path(key));
+    }
+}
+
+static SESSION_STORE: OnceCell<Box<dyn SessionStore>> = OnceCell::new();
+
+// 需要替换默认的文件存储时, 在第一次使用前调用
+pub fn set_session_store(store: Box<dyn SessionStore>) -> bool {
+    SESSION_STORE.set(store).is_ok()
+}
+
+pub fn session_store() -> &'static dyn SessionStore {
+    SESSION_STORE
+        .get_or_init(|| Box::new(FileSessionStore::new(&server_config().session_dir)))
+        .as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_into_cookies() {
+        let url = reqwest::Url::parse("https://codeforces.com/").unwrap();
+        let session = StoredSession {
+            cookies: vec![
+                Cookie::parse("JSESSIONID=a; Path=/", &url).unwrap(),
+                Cookie::parse("39ce7=b; Path=/; Domain=codeforces.com; Max-Age=3600", &url)
+                    .unwrap(),
+            ],
+            saved_at: crate::judger::utils::now_timestamp() - 100,
+        };
+        assert_eq!(session.clone().into_cookies(3600).len(), 2);
+        let cookies = session.into_cookies(10);
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].name(), "39ce7");
+        assert_eq!(cookies[0].domain(), Some("codeforces.com"));
+    }
+}
@@ -1,3 +1,4 @@
+use once_cell::sync::OnceCell;
 use scraper::{ElementRef, Html};
 use std::str::FromStr;
 
@@ -45,3 +46,87 @@ pub fn extract_integer<F: FromStr + Default>(s: &str) -> F {
     let r = s.rfind(|x: char| x.is_digit(10)).unwrap_or(0) + 1;
     s[l..r].parse().unwrap_or_default()
 }
+
+pub fn now_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+// 公历日期转换为距 1970-01-01 的天数
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+//解析 oj 页面上的时间, 支持 "2023-04-21 13:34:06+0900" 与 codeforces 的 "Apr/21/2023 13:34UTC+8"
+//页面未标明时区时使用 default_offset (秒)
+pub fn parse_datetime(s: &str, default_offset: i64) -> Option<i64> {
+    static ISO: OnceCell<regex::Regex> = OnceCell::new();
+    static CF: OnceCell<regex::Regex> = OnceCell::new();
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let iso = ISO.get_or_init(|| {
+        regex::Regex::new(
+            r"(\d{4})[-/](\d{1,2})[-/](\d{1,2})[ T]+(\d{1,2}):(\d{2})(?::(\d{2}))?\s*(?:([+-])(\d{2}):?(\d{2}))?",
+        )
+        .unwrap()
+    });
+    let cf = CF.get_or_init(|| {
+        regex::Regex::new(
+            r"([A-Z][a-z]{2})/(\d{1,2})/(\d{4})\s+(\d{1,2}):(\d{2})(?:\s*UTC([+-]\d+(?:\.\d+)?))?",
+        )
+        .unwrap()
+    });
+
+    let num = |m: Option<regex::Match>| m.map_or(0, |m| m.as_str().parse::<i64>().unwrap_or(0));
+
+    let (y, mon, d, h, min, sec, offset) = if let Some(cap) = iso.captures(s) {
+        let offset = match cap.get(7) {
+            None => default_offset,
+            Some(sign) => {
+                let v = num(cap.get(8)) * 3600 + num(cap.get(9)) * 60;
+                if sign.as_str() == "-" {
+                    -v
+                } else {
+                    v
+                }
+            }
+        };
+        (
+            num(cap.get(1)),
+            num(cap.get(2)),
+            num(cap.get(3)),
+            num(cap.get(4)),
+            num(cap.get(5)),
+            num(cap.get(6)),
+            offset,
+        )
+    } else if let Some(cap) = cf.captures(s) {
+        let mon = MONTHS.iter().position(|&m| m == &cap[1])? as i64 + 1;
+        let offset = cap
+            .get(6)
+            .and_then(|m| m.as_str().parse::<f64>().ok())
+            .map_or(default_offset, |h| (h * 3600.0) as i64);
+        (
+            num(cap.get(3)),
+            mon,
+            num(cap.get(2)),
+            num(cap.get(4)),
+            num(cap.get(5)),
+            0,
+            offset,
+        )
+    } else {
+        return None;
+    };
+
+    Some(days_from_civil(y, mon, d) * 86400 + h * 3600 + min * 60 + sec - offset)
+}
@@ -0,0 +1,139 @@
+// 代码水印: 按语言的注释语法在源码末尾追加唯一标记, 避免重复代码被拒绝, 同时用于识别提交
+use crate::global::{remote_judge_config, remote_judge_constant as constant};
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStyle {
+    Line(&'static str),
+    Block(&'static str, &'static str),
+}
+
+impl CommentStyle {
+    pub fn comment(&self, text: &str) -> String {
+        match self {
+            CommentStyle::Line(start) => format!("{} {}", start, text),
+            CommentStyle::Block(start, end) => format!("{} {} {}", start, text, end),
+        }
+    }
+}
+
+// 按 lang_map 中的语言名称 (c, cpp17, cpp14-noi, python3, pascal ...) 判断注释语法
+pub fn comment_style(lang: &str) -> Option<CommentStyle> {
+    let family: String = lang
+        .to_lowercase()
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    match family.as_str() {
+        "python" | "pypy" | "ruby" | "perl" | "bash" | "r" => Some(CommentStyle::Line("#")),
+        "pascal" | "delphi" | "fpc" => Some(CommentStyle::Block("{", "}")),
+        "ocaml" => Some(CommentStyle::Block("(*", "*)")),
+        "haskell" | "lua" | "ada" => Some(CommentStyle::Line("--")),
+        "c" | "cpp" | "csharp" | "java" | "go" | "rust" | "kotlin" | "scala" | "js"
+        | "javascript" | "node" | "swift" | "d" | "dart" | "php" | "fsharp" => {
+            Some(CommentStyle::Line("//"))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Watermark {
+    pub value: String,
+}
+
+impl Watermark {
+    pub fn generate() -> Self {
+        Self {
+            value: format!("rj-{:016x}", rand::thread_rng().gen::<u64>()),
+        }
+    }
+
+    pub fn apply(&self, source: &str, style: CommentStyle) -> String {
+        let mut res = source.to_string();
+        if !res.is_empty() && !res.ends_with('\n') {
+            res.push('\n');
+        }
+        res.push_str(&style.comment(&self.value));
+        res.push('\n');
+        res
+    }
+}
+
+// 提交时只知道 oj 的语言 id, 反查 lang_map 中对应的语言名称
+pub fn comment_style_for_lang_id(remote_judge_name: &str, lang_id: &str) -> Option<CommentStyle> {
+    let name = if remote_judge_name == constant::names::GYM {
+        constant::names::CODEFORCES
+    } else {
+        remote_judge_name
+    };
+    let config = remote_judge_config(name)?;
+    let mut langs: Vec<&String> = config
+        .lang_map
+        .iter()
+        .filter(|(_, id)| id.as_str() == lang_id)
+        .map(|(lang, _)| lang)
+        .collect();
+    langs.sort();
+    langs
+        .into_iter()
+        .find_map(|lang| comment_style(lang.as_str()))
+}
+
+// 返回加上水印后的代码; 无法确定注释语法时原样返回, 不记录水印
+// codeforces 会拒绝重复的代码, 无法确定时沿用原来的 "//" 注释, 保证每次提交都不相同
+pub fn watermark_source(
+    remote_judge_name: &str,
+    lang_id: &str,
+    source: &str,
+) -> (String, Option<Watermark>) {
+    let style = comment_style_for_lang_id(remote_judge_name, lang_id).or(match remote_judge_name {
+        constant::names::CODEFORCES | constant::names::GYM => Some(CommentStyle::Line("//")),
+        _ => None,
+    });
+    match style {
+        None => (source.to_string(), None),
+        Some(style) => {
+            let watermark = Watermark::generate();
+            (watermark.apply(source, style), Some(watermark))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_comment_style() {
+        assert_eq!(comment_style("cpp17"), Some(CommentStyle::Line("//")));
+        assert_eq!(comment_style("cpp14-noi"), Some(CommentStyle::Line("//")));
+        assert_eq!(comment_style("csharp"), Some(CommentStyle::Line("//")));
+        assert_eq!(comment_style("python3"), Some(CommentStyle::Line("#")));
+        assert_eq!(comment_style("rust"), Some(CommentStyle::Line("//")));
+        assert_eq!(comment_style("pascal"), Some(CommentStyle::Block("{", "}")));
+        assert_eq!(
+            comment_style("ocaml"),
+            Some(CommentStyle::Block("(*", "*)"))
+        );
+        assert_eq!(comment_style("brainfuck"), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let w = Watermark {
+            value: "rj-1".into(),
+        };
+        assert_eq!(
+            w.apply("print(1)", CommentStyle::Line("#")),
+            "print(1)\n# rj-1\n"
+        );
+        assert_eq!(
+            w.apply("begin end.\n", CommentStyle::Block("{", "}")),
+            "begin end.\n{ rj-1 }\n"
+        );
+    }
+}
@@ -25,6 +25,7 @@ async fn main() -> anyhow::Result<()> {
     };
 
     global::init_config(server_path, config_path, logger_path).await;
-    server::make_ws_server().await;
+    server::recover_jobs().await;
+    tokio::join!(server::make_ws_server(), server::make_grpc_server());
     Ok(())
 }
@@ -0,0 +1,342 @@
+// 鉴权: ACCESS_TOKEN 请求头 (全局 access_token 或某个 api key), 或者由服务端密钥签名的短期 ticket
+// 浏览器无法在 web-socket 握手时设置请求头, 因此 ticket 可以通过 ?ticket= 或 Sec-WebSocket-Protocol: ticket.<ticket> 传递
+use super::job::JobView;
+use super::{ApiKey, Priority, WsRequest};
+use crate::global::server_config;
+use crate::judger::provider::SubmissionHandle;
+use crate::judger::utils::now_timestamp;
+
+use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const TICKET_PROTOCOL_PREFIX: &str = "ticket.";
+// 签发 ticket 的最长有效期, 秒
+pub const MAX_TICKET_TTL: u64 = 3600;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TicketClaims {
+    pub exp: i64,         // 过期时间, unix 时间戳, 秒
+    pub ojs: Vec<String>, // 允许使用的 oj
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>, // 签发该 ticket 的 api key, 沿用其限制
+}
+
+pub fn mac(secret: &str, payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac 支持任意长度的密钥");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+// ticket 格式: base64url(claims json) + "." + base64url(hmac-sha256)
+pub fn issue_ticket(secret: &str, claims: &TicketClaims) -> String {
+    let payload = base64_url::encode(&serde_json::to_vec(claims).unwrap_or_default());
+    let signature = base64_url::encode(&mac(secret, &payload).finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+// 用于区分 ticket 的标识, 会记录在任务中, 因此取签名的摘要而不是签名本身
+pub fn ticket_id(ticket: &str) -> String {
+    let signature = ticket.rsplit_once('.').map_or(ticket, |(_, signature)| signature);
+    Sha256::digest(signature.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// handle 的签名密钥, 都没有配置时不签名
+pub fn handle_secret() -> Option<&'static str> {
+    let config = server_config();
+    config
+        .ticket_secret
+        .as_deref()
+        .or(config.access_token.as_deref())
+}
+
+// 返回给客户端的 handle 带有签名, 防止客户端修改账户等字段后让服务端用其他账户 poll
+// 格式: SubmissionHandle::encode() + "." + base64url(hmac-sha256)
+pub fn sign_handle(secret: Option<&str>, handle: &SubmissionHandle) -> String {
+    let payload = handle.encode();
+    match secret {
+        None => payload,
+        Some(secret) => {
+            let signature = base64_url::encode(&mac(secret, &payload).finalize().into_bytes());
+            format!("{}.{}", payload, signature)
+        }
+    }
+}
+
+pub fn verify_handle(secret: Option<&str>, token: &str) -> anyhow::Result<SubmissionHandle> {
+    let (payload, signature) = match token.split_once('.') {
+        Some((payload, signature)) => (payload, Some(signature)),
+        None => (token, None),
+    };
+    if let Some(secret) = secret {
+        let signature = signature
+            .and_then(|s| base64_url::decode(s).ok())
+            .ok_or(anyhow!("handle 签名错误"))?;
+        mac(secret, payload)
+            .verify_slice(&signature)
+            .map_err(|_| anyhow!("handle 签名错误"))?;
+    }
+    SubmissionHandle::decode(payload).map_err(|_| anyhow!("handle 格式错误"))
+}
+
+pub fn verify_ticket(secret: &str, ticket: &str) -> anyhow::Result<TicketClaims> {
+    let (payload, signature) = ticket.split_once('.').ok_or(anyhow!("ticket 格式错误"))?;
+    let signature = base64_url::decode(signature).map_err(|_| anyhow!("ticket 格式错误"))?;
+
+    // verify_slice 为常量时间比较
+    mac(secret, payload)
+        .verify_slice(&signature)
+        .map_err(|_| anyhow!("ticket 签名错误"))?;
+
+    let claims: TicketClaims = serde_json::from_slice(
+        &base64_url::decode(payload).map_err(|_| anyhow!("ticket 格式错误"))?,
+    )?;
+    if claims.exp <= now_timestamp() {
+        return Err(anyhow!("ticket 已过期"));
+    }
+    Ok(claims)
+}
+
+// 鉴权结果, 由中间件放入请求的 extensions
+#[derive(Debug, Clone, Default)]
+pub struct AuthContext {
+    pub key: Option<String>,      // api key 名称, None 表示全局 access_token 或未开启鉴权
+    pub ojs: Option<Vec<String>>, // None 表示不限制
+    pub request_types: Option<Vec<String>>,
+    pub priorities: Option<Vec<Priority>>,
+    pub expires_at: Option<i64>,
+    pub protocol: Option<String>, // 通过 Sec-WebSocket-Protocol 传递 ticket 时需要原样返回
+    pub client: Option<String>,   // 没有 api key 时用于公平排队的客户端标识: ticket, 连接等
+}
+
+impl AuthContext {
+    pub fn unrestricted() -> Self {
+        Self::default()
+    }
+
+    pub fn from_key(key: &ApiKey) -> Self {
+        Self {
+            key: Some(key.name.clone()),
+            ojs: key.ojs.clone(),
+            request_types: key.request_types.clone(),
+            priorities: Some(
+                key.priorities
+                    .clone()
+                    .unwrap_or_else(|| vec![Priority::Practice, Priority::Rejudge]),
+            ),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_client(mut self, client: String) -> Self {
+        self.client.get_or_insert(client);
+        self
+    }
+
+    pub fn from_ticket(claims: TicketClaims, protocol: Option<String>) -> anyhow::Result<Self> {
+        let mut ctx = match claims.key.as_ref() {
+            // 没有 api key 的 ticket 只能使用默认优先级
+            None => Self {
+                priorities: Some(vec![Priority::default()]),
+                ..Default::default()
+            },
+            // key 被删除后由它签发的 ticket 一并失效
+            Some(name) => Self::from_key(
+                server_config()
+                    .api_key(name)
+                    .ok_or(anyhow!("api key {} 不存在", name))?,
+            ),
+        };
+        // ticket 只能缩小签发 key 的权限
+        let ojs: Vec<String> = match ctx.ojs.as_ref() {
+            None => claims.ojs,
+            Some(allowed) => claims
+                .ojs
+                .into_iter()
+                .filter(|oj| allowed.contains(oj))
+                .collect(),
+        };
+        if ojs.is_empty() {
+            return Err(anyhow!("ticket 没有可用的 oj"));
+        }
+        ctx.ojs = Some(ojs);
+        ctx.expires_at = Some(claims.exp);
+        ctx.protocol = protocol;
+        Ok(ctx)
+    }
+
+    // 日志与任务记录中使用的名称
+    pub fn name(&self) -> &str {
+        self.key.as_deref().unwrap_or("-")
+    }
+
+    // 提交队列按该标识做公平排队: 同一 api key 的所有连接共用, 否则按 ticket 或连接区分
+    pub fn client(&self) -> &str {
+        self.key
+            .as_deref()
+            .or(self.client.as_deref())
+            .unwrap_or("-")
+    }
+
+    pub fn is_ticket(&self) -> bool {
+        self.expires_at.is_some()
+    }
+
+    // 没有 api key 的 ticket 创建的任务记录 ticket 标识, 只有该 ticket 可以查看
+    pub fn owner(&self) -> Option<String> {
+        match self.is_ticket() && self.key.is_none() {
+            true => self.client.clone(),
+            false => None,
+        }
+    }
+
+    // 长连接上的每个请求都要检查, ticket 过期后不再接受新的请求
+    pub fn check(&self, req: &WsRequest) -> anyhow::Result<()> {
+        if let Some(exp) = self.expires_at {
+            if exp <= now_timestamp() {
+                return Err(anyhow!("ticket 已过期"));
+            }
+        }
+        if let Some(ojs) = self.ojs.as_ref() {
+            if !ojs.iter().any(|oj| oj == &req.remote_judge) {
+                return Err(anyhow!("没有使用 {} 的权限", req.remote_judge));
+            }
+        }
+        if let Some(types) = self.request_types.as_ref() {
+            if !types.iter().any(|t| t == &req.request_type) {
+                return Err(anyhow!("没有 {} 请求的权限", req.request_type));
+            }
+        }
+        if let Some(priorities) = self.priorities.as_ref() {
+            if !priorities.contains(&req.priority) {
+                return Err(anyhow!("没有使用 {:?} 优先级的权限", req.priority));
+            }
+        }
+        Ok(())
+    }
+
+    // 只能查看自己创建的, 有权限的 oj 上的任务; 全局 access_token 可以查看所有 key 的任务
+    pub fn can_view(&self, job: &JobView) -> bool {
+        if self.key.is_some() && self.key != job.key {
+            return false;
+        }
+        if let Some(owner) = self.owner() {
+            if job.owner.as_ref() != Some(&owner) {
+                return false;
+            }
+        }
+        self.ojs
+            .as_ref()
+            .map_or(true, |ojs| ojs.iter().any(|oj| oj == &job.remote_judge))
+    }
+}
+
+// 按 ACCESS_TOKEN 鉴权, 未配置 access_token 与 api key 时不限制
+pub fn authenticate(token: Option<&str>) -> Option<AuthContext> {
+    let config = server_config();
+    if config.access_token.is_none() && config.api_keys.is_empty() {
+        return Some(AuthContext::unrestricted());
+    }
+    let token = token?;
+    if config.access_token.as_deref() == Some(token) {
+        return Some(AuthContext::unrestricted());
+    }
+    config
+        .api_keys
+        .iter()
+        .find(|k| k.key == token)
+        .map(AuthContext::from_key)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_ticket() {
+        let claims = TicketClaims {
+            exp: now_timestamp() + 60,
+            ojs: vec!["hdu".into()],
+            key: None,
+        };
+        let ticket = issue_ticket("secret", &claims);
+        assert_eq!(verify_ticket("secret", &ticket).unwrap(), claims);
+        assert!(verify_ticket("other", &ticket).is_err());
+
+        let (payload, signature) = ticket.split_once('.').unwrap();
+        let forged = TicketClaims {
+            ojs: vec!["hdu".into(), "codeforces".into()],
+            ..claims.clone()
+        };
+        let forged_payload = base64_url::encode(&serde_json::to_vec(&forged).unwrap());
+        assert_ne!(payload, forged_payload);
+        assert!(verify_ticket("secret", &format!("{}.{}", forged_payload, signature)).is_err());
+
+        let expired = TicketClaims {
+            exp: now_timestamp() - 1,
+            ..claims
+        };
+        assert!(verify_ticket("secret", &issue_ticket("secret", &expired)).is_err());
+    }
+
+    #[test]
+    fn test_handle() {
+        let handle = SubmissionHandle::from_remote_id("hdu", "a", "1000", "42");
+        let token = sign_handle(Some("secret"), &handle);
+        assert_eq!(
+            verify_handle(Some("secret"), &token).unwrap().account,
+            "a"
+        );
+        assert!(verify_handle(Some("other"), &token).is_err());
+
+        let forged = SubmissionHandle {
+            account: "b".into(),
+            ..handle.clone()
+        };
+        let signature = token.split_once('.').unwrap().1;
+        let forged = format!("{}.{}", forged.encode(), signature);
+        assert!(verify_handle(Some("secret"), &forged).is_err());
+        assert!(verify_handle(Some("secret"), &handle.encode()).is_err());
+        assert!(verify_handle(None, &handle.encode()).is_ok());
+    }
+
+    #[test]
+    fn test_keyless_ticket() {
+        let claims = TicketClaims {
+            exp: now_timestamp() + 60,
+            ojs: vec!["hdu".into()],
+            key: None,
+        };
+        let ctx = AuthContext::from_ticket(claims, None)
+            .unwrap()
+            .with_client("ticket:a".into());
+        let mut req = WsRequest {
+            remote_judge: "hdu".into(),
+            request_type: "judge".into(),
+            priority: Priority::Contest,
+            ..Default::default()
+        };
+        assert!(ctx.check(&req).is_err());
+        req.priority = Priority::default();
+        assert!(ctx.check(&req).is_ok());
+
+        let mut job: JobView = serde_json::from_value(serde_json::json!({
+            "id": "j1", "key": null, "owner": "ticket:a", "remote_judge": "hdu",
+            "request_type": "judge", "state": "running", "created_at": 1, "updated_at": 1,
+            "submission_id": null, "handle": null, "status": null, "problem": null, "error": null
+        }))
+        .unwrap();
+        assert!(ctx.can_view(&job));
+        job.owner = Some("ticket:b".into());
+        assert!(!ctx.can_view(&job));
+        assert!(AuthContext::unrestricted().can_view(&job));
+    }
+}
@@ -1,24 +1,114 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ServerConfig {
     pub host: String,
     pub ws_port: String,
+    pub grpc_port: Option<String>, // 不配置时不启动 gRPC 服务
     pub access_token: Option<String>,
+    pub ticket_secret: Option<String>, // 签发浏览器使用的 ticket 和 handle, 不配置时不接受 ticket, handle 改用 access_token 签名
     pub max_poll_times: usize,
     pub max_wait_time: u32,
     pub wait_incr: u32,
     pub wait_base: u32,
+    #[serde(default = "default_session_dir")]
+    pub session_dir: String,
+    #[serde(default = "default_job_ttl")]
+    pub job_ttl: u64, // 已结束的任务在内存中保留的时长, 秒; 之后从 job_db 中读取
+    #[serde(default = "default_job_db")]
+    pub job_db: String, // sqlite 数据库文件
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    pub webhook_secret: Option<String>, // 回调请求的签名密钥, 不配置时不签名
+    #[serde(default)]
+    pub webhook_allow_hosts: Vec<String>, // 允许回调的本机或内网 host, 其他指向内网的回调地址一律拒绝
+    #[serde(default)]
+    pub poll_rates: HashMap<String, f64>, // 每个 oj 每秒最多 poll 的次数
+    #[serde(default)]
+    pub submit_concurrency: HashMap<String, usize>, // 每个 oj 同时进行的提交数, 默认为账户数
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl ServerConfig {
+    pub fn api_key(&self, name: &str) -> Option<&ApiKey> {
+        self.api_keys.iter().find(|k| k.name == name)
+    }
+}
+
+// 每个客户端一个 key, 未配置的限制项表示不限制
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    pub ojs: Option<Vec<String>>,
+    pub request_types: Option<Vec<String>>,
+    pub max_concurrent: Option<usize>,
+    pub daily_submissions: Option<u32>, // 按 UTC 自然日计算, 只计入提交成功的次数
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>, // 该 key 创建的每个任务都会回调
+    pub priorities: Option<Vec<Priority>>, // 可以使用的提交优先级, 不配置时只能使用 practice 与 rejudge
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub updates: bool, // 是否推送中间状态, 默认只推送最终结果
+}
+
+fn default_session_dir() -> String {
+    "sessions".into()
+}
+
+fn default_job_ttl() -> u64 {
+    3600
+}
+
+fn default_job_db() -> String {
+    "jobs.db".into()
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct WsRequest {
+    // 同一连接上并发多个请求时用于区分回复, 不提供时处理完该请求后关闭连接
+    #[serde(default)]
+    pub request_id: Option<String>,
     pub remote_judge: String,
     pub request_type: String, // judge
 
     pub lang: Option<String>,
     pub problem_id: Option<String>,
     pub source: Option<String>,
+    #[serde(default)]
+    pub priority: Priority, // judge 请求在提交队列中的优先级
+
+    // track: 提供 handle, 或者提供 submission_id 以及 problem_id, account
+    pub submission_id: Option<String>,
+    pub handle: Option<String>,
+    pub account: Option<String>,
+
+    // subscribe: 订阅已有的任务
+    #[serde(default)]
+    pub job_id: Option<String>,
+
+    // 任务结束时把结果 POST 到该地址, 客户端不需要一直保持连接
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    #[serde(default)]
+    pub callback_updates: bool, // 同时推送中间状态
+}
+
+// 提交队列的优先级, 从高到低
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Contest,
+    #[default]
+    Practice,
+    Rejudge,
 }
 
 #[cfg(test)]
@@ -29,11 +119,19 @@ mod tests {
     #[test]
     fn test_req() {
         let req = WsRequest {
+            request_id: Some("1".into()),
             remote_judge: "atcoder".into(),
             request_type: "judge".into(),
             lang: Some("CPP".into()),
             problem_id: Some("arc159_f".into()),
             source: Some(include_str!("../code.txt").into()),
+            priority: Priority::Contest,
+            submission_id: None,
+            handle: None,
+            account: None,
+            job_id: None,
+            callback_url: None,
+            callback_updates: false,
         };
         println!("{}", serde_json::json!(req).to_string());
     }
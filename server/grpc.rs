@@ -0,0 +1,243 @@
+// gRPC 服务, 与 web-socket / REST 共用 server::task 的逻辑
+use super::auth::{self, AuthContext};
+use super::protocol::ServerMessage;
+use super::reply::Reply;
+use super::server::execute;
+use super::{Priority, WsRequest};
+use crate::global::{self, task_constant::names as task_names};
+use crate::judger::{provider, utils::breaker::CIRCUIT_OPEN};
+
+use simple_log::log::info;
+use std::pin::Pin;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream, StreamExt};
+use tonic::{transport::Server, Request, Response, Status};
+
+// 由 proto/remote_judge.proto 生成
+mod pb {
+    include!("proto/remote_judge.rs");
+}
+
+use pb::judge_event::Event;
+use pb::remote_judge_server::{RemoteJudge, RemoteJudgeServer};
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<pb::JudgeEvent, Status>> + Send>>;
+
+impl From<provider::SubmissionStatus> for pb::SubmissionStatus {
+    fn from(s: provider::SubmissionStatus) -> Self {
+        Self {
+            submission_id: s.submission_id,
+            status: s.status.to_string(),
+            info: s.info,
+            is_over: s.is_over,
+            score: s.score as u32,
+            time: s.time,
+            memory: s.memory,
+            compile_message: s.compile.map(|c| c.message),
+            judge_json: s.judge.and_then(|j| serde_json::to_string(&j).ok()),
+        }
+    }
+}
+
+impl From<provider::Problem> for pb::Problem {
+    fn from(p: provider::Problem) -> Self {
+        Self {
+            problem_id: p.problem_id,
+            title: p.title,
+            time_limit: p.time_limit,
+            memory_limit: p.memory_limit,
+            description: p.description,
+            input_format: p.input_format,
+            output_format: p.output_format,
+            limit_and_hint: p.limit_and_hint,
+            examples_input: p.examples_input,
+            examples_output: p.examples_output,
+            others: p.others,
+        }
+    }
+}
+
+// 拦截器放入的鉴权结果
+// 没有 api key 的客户端按来源地址公平排队
+fn auth_of<T>(request: &Request<T>) -> AuthContext {
+    let auth = request
+        .extensions()
+        .get::<AuthContext>()
+        .cloned()
+        .unwrap_or_default();
+    match request.remote_addr() {
+        Some(addr) => auth.with_client(format!("grpc:{}", addr)),
+        None => auth,
+    }
+}
+
+// 在后台执行请求, 返回该请求的全部回复; 接收端被丢弃 (客户端断开) 后任务随之结束
+fn start(auth: AuthContext, req: WsRequest) -> mpsc::UnboundedReceiver<ServerMessage> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        execute(&Reply::stream(tx), req, &auth).await;
+    });
+    rx
+}
+
+// 远程 oj 熔断时返回 UNAVAILABLE, 客户端可以稍后重试
+fn error_status(message: String, code: Option<String>) -> Status {
+    match code.as_deref() {
+        Some(CIRCUIT_OPEN) => Status::unavailable(message),
+        _ => Status::internal(message),
+    }
+}
+
+fn event_stream(rx: mpsc::UnboundedReceiver<ServerMessage>) -> EventStream {
+    let stream = UnboundedReceiverStream::new(rx).filter_map(|msg| {
+        let event = match msg {
+            ServerMessage::Accepted {
+                submission_id,
+                account,
+                handle,
+                job_id,
+            } => Event::Accepted(pb::Accepted {
+                submission_id,
+                account,
+                handle,
+                job_id,
+            }),
+            ServerMessage::Status(status) => Event::Status(status.into()),
+            ServerMessage::Queued {
+                position,
+                estimated_wait,
+            } => Event::Queued(pb::Queued {
+                position: position as u32,
+                estimated_wait,
+            }),
+            ServerMessage::Error { message, code } => {
+                return Some(Err(error_status(message, code)))
+            }
+            _ => return None,
+        };
+        Some(Ok(pb::JudgeEvent { event: Some(event) }))
+    });
+    Box::pin(stream)
+}
+
+pub struct RemoteJudgeService;
+
+#[tonic::async_trait]
+impl RemoteJudge for RemoteJudgeService {
+    type SubmitAndWatchStream = EventStream;
+    type TrackStream = EventStream;
+    type SubscribeStream = EventStream;
+
+    async fn submit_and_watch(
+        &self,
+        request: Request<pb::SubmitRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        let auth = auth_of(&request);
+        let r = request.into_inner();
+        let req = WsRequest {
+            remote_judge: r.remote_judge,
+            request_type: task_names::JUDGE.into(),
+            problem_id: Some(r.problem_id),
+            lang: Some(r.lang),
+            source: Some(r.source),
+            priority: match r.priority {
+                Some(p) => serde_json::from_value(p.into())
+                    .map_err(|_| Status::invalid_argument("priority 错误"))?,
+                None => Priority::default(),
+            },
+            ..Default::default()
+        };
+        Ok(Response::new(event_stream(start(auth, req))))
+    }
+
+    async fn get_problem(
+        &self,
+        request: Request<pb::ProblemRequest>,
+    ) -> Result<Response<pb::Problem>, Status> {
+        let auth = auth_of(&request);
+        let r = request.into_inner();
+        let req = WsRequest {
+            remote_judge: r.remote_judge,
+            request_type: task_names::GET_PROBLEM.into(),
+            problem_id: Some(r.problem_id),
+            ..Default::default()
+        };
+        let mut rx = start(auth, req);
+        while let Some(msg) = rx.recv().await {
+            match msg {
+                ServerMessage::Problem(problem) => return Ok(Response::new(problem.into())),
+                ServerMessage::Error { message, code } => return Err(error_status(message, code)),
+                _ => {}
+            }
+        }
+        Err(Status::internal("获取题目失败"))
+    }
+
+    async fn track(
+        &self,
+        request: Request<pb::TrackRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        let auth = auth_of(&request);
+        let r = request.into_inner();
+        let req = WsRequest {
+            remote_judge: r.remote_judge,
+            request_type: task_names::TRACK.into(),
+            handle: r.handle,
+            submission_id: r.submission_id,
+            problem_id: r.problem_id,
+            account: r.account,
+            ..Default::default()
+        };
+        Ok(Response::new(event_stream(start(auth, req))))
+    }
+
+    async fn subscribe(
+        &self,
+        request: Request<pb::SubscribeRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        let auth = auth_of(&request);
+        let r = request.into_inner();
+        let req = WsRequest {
+            remote_judge: r.remote_judge,
+            request_type: task_names::SUBSCRIBE.into(),
+            job_id: Some(r.job_id),
+            ..Default::default()
+        };
+        Ok(Response::new(event_stream(start(auth, req))))
+    }
+}
+
+// 与 web-socket 服务使用同样的 access_token / api key, 通过 metadata access_token 传递
+#[allow(clippy::result_large_err)]
+fn check_access_token(mut req: Request<()>) -> Result<Request<()>, Status> {
+    let token = req
+        .metadata()
+        .get("access_token")
+        .and_then(|v| v.to_str().ok());
+    match auth::authenticate(token) {
+        Some(ctx) => {
+            req.extensions_mut().insert(ctx);
+            Ok(req)
+        }
+        None => Err(Status::unauthenticated("没有权限")),
+    }
+}
+
+// 未配置 grpc_port 时不启动
+pub async fn make_grpc_server() {
+    let config = global::server_config();
+    let Some(port) = config.grpc_port.as_ref() else {
+        return;
+    };
+    let grpc_addr = format!("{}:{}", config.host, port);
+
+    info!("gRPC服务: {}", grpc_addr);
+    Server::builder()
+        .add_service(RemoteJudgeServer::with_interceptor(
+            RemoteJudgeService,
+            check_access_token,
+        ))
+        .serve(grpc_addr.parse().unwrap())
+        .await
+        .unwrap();
+}
@@ -0,0 +1,321 @@
+// 评测任务: 记录任务的全部消息并写入 JobStore, 支持查询最新状态与 SSE 订阅
+// 通过 REST 接口提交的请求, 以及 web-socket / gRPC 上的 judge / track 请求都会创建任务, 客户端断开后任务继续执行
+use super::protocol::{Envelope, ServerMessage, PROTOCOL_VERSION};
+use super::store::{job_store, JobStore};
+use super::webhook::{self, Notifier};
+use super::WsRequest;
+use crate::global::server_config;
+use crate::judger::provider::{Problem, SubmissionStatus};
+use crate::judger::utils::now_timestamp;
+
+use once_cell::sync::OnceCell;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use simple_log::log::error;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+}
+
+// GET /jobs/{id} 返回的任务概况
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JobView {
+    pub id: String,
+    pub key: Option<String>, // 创建任务的 api key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>, // 没有 api key 的 ticket 创建的任务, 记录 ticket 标识
+    pub remote_judge: String,
+    pub request_type: String,
+    pub state: JobState,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub submission_id: Option<String>,
+    pub handle: Option<String>,
+    pub status: Option<SubmissionStatus>,
+    pub problem: Option<Problem>,
+    pub error: Option<String>,
+}
+
+struct JobInner {
+    view: JobView,
+    history: Vec<Envelope>,
+    tx: Option<broadcast::Sender<Envelope>>, // 任务结束后置为 None, 订阅者随之结束
+}
+
+pub struct Job {
+    pub id: String,
+    inner: Mutex<JobInner>,
+    // 不写入 JobStore 时为 None
+    writer: Option<mpsc::UnboundedSender<Write>>,
+    notifier: Option<Notifier>, // 没有回调地址时为 None
+}
+
+enum Write {
+    Insert(JobView, WsRequest),
+    Append(JobView, Envelope),
+}
+
+// 任务和消息按顺序在阻塞线程中写入 JobStore, 创建任务和 push 时都不等待写入完成
+fn spawn_writer(id: &str, store: &'static dyn JobStore) -> mpsc::UnboundedSender<Write> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Write>();
+    let id = id.to_string();
+    tokio::spawn(async move {
+        while let Some(write) = rx.recv().await {
+            let res = tokio::task::spawn_blocking(move || match write {
+                Write::Insert(view, req) => store.insert(&view, &req),
+                Write::Append(view, envelope) => store.append(&view, &envelope),
+            })
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|res| res);
+            if let Err(e) = res {
+                error!("保存任务 {} 失败: {}", id, e);
+            }
+        }
+    });
+    tx
+}
+
+impl Job {
+    fn new(
+        req: &WsRequest,
+        key: Option<String>,
+        owner: Option<String>,
+        store: Option<&'static dyn JobStore>,
+    ) -> Self {
+        let id = format!("{:016x}", rand::thread_rng().gen::<u64>());
+        let now = now_timestamp();
+        let (tx, _) = broadcast::channel(64);
+        let notifier = Notifier::new(&id, webhook::targets(req, key.as_deref()));
+        let writer = store.map(|store| spawn_writer(&id, store));
+        let job = Self {
+            id: id.clone(),
+            inner: Mutex::new(JobInner {
+                view: JobView {
+                    id,
+                    key,
+                    owner,
+                    remote_judge: req.remote_judge.clone(),
+                    request_type: req.request_type.clone(),
+                    state: JobState::Running,
+                    created_at: now,
+                    updated_at: now,
+                    submission_id: None,
+                    handle: None,
+                    status: None,
+                    problem: None,
+                    error: None,
+                },
+                history: vec![],
+                tx: Some(tx),
+            }),
+            writer,
+            notifier,
+        };
+        // insert 与之后的 append 走同一个 writer, 保证先于消息写入
+        if let Some(writer) = job.writer.as_ref() {
+            let _ = writer.send(Write::Insert(job.view(), req.clone()));
+        }
+        job
+    }
+
+    // 从 JobStore 中读出的任务; 不提供 store 时只用于查询, 不会再有新的消息
+    fn restore(
+        view: JobView,
+        history: Vec<Envelope>,
+        store: Option<&'static dyn JobStore>,
+    ) -> Self {
+        let tx = match store {
+            Some(_) if view.state == JobState::Running => Some(broadcast::channel(64).0),
+            _ => None,
+        };
+        Self {
+            id: view.id.clone(),
+            writer: store.map(|store| spawn_writer(&view.id, store)),
+            inner: Mutex::new(JobInner { view, history, tx }),
+            notifier: None,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JobInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn view(&self) -> JobView {
+        self.lock().view.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.lock().view.state != JobState::Running
+    }
+
+    pub fn push(&self, message: ServerMessage) {
+        let mut inner = self.lock();
+        let view = &mut inner.view;
+        view.updated_at = now_timestamp();
+        match &message {
+            ServerMessage::Accepted {
+                submission_id,
+                handle,
+                ..
+            } => {
+                view.submission_id = Some(submission_id.clone());
+                view.handle = Some(handle.clone());
+            }
+            ServerMessage::Status(status) => view.status = Some(status.clone()),
+            ServerMessage::Problem(problem) => view.problem = Some(problem.clone()),
+            ServerMessage::Error { message, .. } => {
+                view.error = Some(message.clone());
+                view.state = JobState::Failed;
+            }
+            ServerMessage::Done => view.state = JobState::Done,
+            ServerMessage::Hello { .. } | ServerMessage::Queued { .. } => {}
+        }
+
+        let envelope = Envelope {
+            version: PROTOCOL_VERSION,
+            seq: inner.history.len() as u64,
+            request_id: None,
+            message,
+        };
+        if let Some(writer) = self.writer.as_ref() {
+            let _ = writer.send(Write::Append(inner.view.clone(), envelope.clone()));
+        }
+        if let Some(notifier) = self.notifier.as_ref() {
+            let is_status = matches!(envelope.message, ServerMessage::Status(_));
+            notifier.notify(&inner.view, is_status);
+        }
+        inner.history.push(envelope.clone());
+        if let Some(tx) = inner.tx.as_ref() {
+            let _ = tx.send(envelope);
+        }
+        if inner.view.state != JobState::Running {
+            inner.tx.take();
+        }
+    }
+
+    // 迟到的订阅者只需要提交信息与最新状态, 任务已结束时附带最后的 done / error
+    pub fn watch(&self) -> (Vec<ServerMessage>, Option<broadcast::Receiver<Envelope>>) {
+        let inner = self.lock();
+        let latest = |f: fn(&ServerMessage) -> bool| {
+            inner
+                .history
+                .iter()
+                .rev()
+                .map(|e| &e.message)
+                .find(|m| f(m))
+                .cloned()
+        };
+        let mut messages: Vec<ServerMessage> = [
+            latest(|m| matches!(m, ServerMessage::Accepted { .. })),
+            latest(|m| matches!(m, ServerMessage::Status(_) | ServerMessage::Problem(_))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if inner.view.state != JobState::Running {
+            messages.extend(inner.history.last().map(|e| e.message.clone()));
+        }
+        (messages, inner.tx.as_ref().map(|tx| tx.subscribe()))
+    }
+
+    // 返回已有的消息以及后续消息的订阅, 任务已结束时订阅为 None
+    pub fn subscribe(&self) -> (Vec<Envelope>, Option<broadcast::Receiver<Envelope>>) {
+        let inner = self.lock();
+        (
+            inner.history.clone(),
+            inner.tx.as_ref().map(|tx| tx.subscribe()),
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+}
+
+impl JobRegistry {
+    pub fn create(&self, req: &WsRequest, key: Option<String>, owner: Option<String>) -> Arc<Job> {
+        let job = Arc::new(Job::new(req, key, owner, Some(job_store())));
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        // 顺便清理过期的已结束任务
+        let expire = now_timestamp() - server_config().job_ttl as i64;
+        jobs.retain(|_, j| !j.is_finished() || j.view().updated_at > expire);
+        jobs.insert(job.id.clone(), job.clone());
+        job
+    }
+
+    // 服务重启后继续执行的任务, 沿用原来的 id 与消息序号
+    pub fn resume(&self, view: JobView, req: &WsRequest, history: Vec<Envelope>) -> Arc<Job> {
+        let mut job = Job::restore(view, history, Some(job_store()));
+        job.notifier = Notifier::new(&job.id, webhook::targets(req, job.view().key.as_deref()));
+        let job = Arc::new(job);
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(job.id.clone(), job.clone());
+        job
+    }
+
+    // 内存中已清理的任务从 JobStore 中读取
+    pub fn get(&self, id: &str) -> Option<Arc<Job>> {
+        if let Some(job) = self.jobs.lock().unwrap_or_else(|e| e.into_inner()).get(id) {
+            return Some(job.clone());
+        }
+        match job_store().load(id) {
+            Ok(job) => job.map(|(view, history)| Arc::new(Job::restore(view, history, None))),
+            Err(e) => {
+                error!("读取任务 {} 失败: {}", id, e);
+                None
+            }
+        }
+    }
+}
+
+pub fn jobs() -> &'static JobRegistry {
+    static JOBS: OnceCell<JobRegistry> = OnceCell::new();
+    JOBS.get_or_init(JobRegistry::default)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_job_push() {
+        let req: WsRequest = serde_json::from_str(
+            r#"{"remote_judge": "hdu", "request_type": "judge", "problem_id": "1000"}"#,
+        )
+        .unwrap();
+        let job = Job::new(&req, None, None, None);
+        job.push(ServerMessage::Accepted {
+            submission_id: "42".into(),
+            account: "a".into(),
+            handle: "h".into(),
+            job_id: Some(job.id.clone()),
+        });
+        let (history, rx) = job.subscribe();
+        assert_eq!(history.len(), 1);
+        assert!(rx.is_some());
+        assert_eq!(job.watch().0.len(), 1);
+
+        job.push(ServerMessage::Done);
+        let view = job.view();
+        assert_eq!(view.state, JobState::Done);
+        assert_eq!(view.submission_id.as_deref(), Some("42"));
+        let (history, rx) = job.subscribe();
+        assert_eq!(history.last().unwrap().seq, 1);
+        assert!(rx.is_none());
+        let (latest, rx) = job.watch();
+        assert!(matches!(latest.last(), Some(ServerMessage::Done)));
+        assert!(rx.is_none());
+    }
+}
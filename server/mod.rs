@@ -1,6 +1,18 @@
 mod accquire;
+mod auth;
 mod definition;
+mod grpc;
+mod job;
+mod protocol;
+mod queue;
+mod quota;
+mod reply;
+mod rest;
+mod scheduler;
 mod server;
+mod store;
 mod task;
+mod webhook;
 pub use definition::*;
-pub use server::make_ws_server;
+pub use grpc::make_grpc_server;
+pub use server::{make_ws_server, recover_jobs};
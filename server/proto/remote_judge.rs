@@ -0,0 +1,493 @@
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubmitRequest {
+    #[prost(string, tag = "1")]
+    pub remote_judge: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub problem_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub lang: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub source: ::prost::alloc::string::String,
+    /// contest / practice / rejudge, 默认 practice
+    #[prost(string, optional, tag = "5")]
+    pub priority: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProblemRequest {
+    #[prost(string, tag = "1")]
+    pub remote_judge: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub problem_id: ::prost::alloc::string::String,
+}
+/// 提供 handle, 或者提供 submission_id, problem_id 与 account
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TrackRequest {
+    #[prost(string, tag = "1")]
+    pub remote_judge: ::prost::alloc::string::String,
+    #[prost(string, optional, tag = "2")]
+    pub handle: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "3")]
+    pub submission_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "4")]
+    pub problem_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "5")]
+    pub account: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubscribeRequest {
+    #[prost(string, tag = "1")]
+    pub remote_judge: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub job_id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Accepted {
+    #[prost(string, tag = "1")]
+    pub submission_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub account: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub handle: ::prost::alloc::string::String,
+    /// 客户端断开后可通过 GET /jobs/{id} 查询结果
+    #[prost(string, optional, tag = "4")]
+    pub job_id: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SubmissionStatus {
+    #[prost(string, tag = "1")]
+    pub submission_id: ::prost::alloc::string::String,
+    /// 与 web-socket 协议中的测评结果字符串相同
+    #[prost(string, tag = "2")]
+    pub status: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub info: ::prost::alloc::string::String,
+    #[prost(bool, tag = "4")]
+    pub is_over: bool,
+    #[prost(uint32, tag = "5")]
+    pub score: u32,
+    /// MS
+    #[prost(uint32, tag = "6")]
+    pub time: u32,
+    /// KB
+    #[prost(uint32, tag = "7")]
+    pub memory: u32,
+    #[prost(string, optional, tag = "8")]
+    pub compile_message: ::core::option::Option<::prost::alloc::string::String>,
+    /// JudgeDetail 的 json
+    #[prost(string, optional, tag = "9")]
+    pub judge_json: ::core::option::Option<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JudgeEvent {
+    #[prost(oneof = "judge_event::Event", tags = "1, 2, 3")]
+    pub event: ::core::option::Option<judge_event::Event>,
+}
+/// Nested message and enum types in `JudgeEvent`.
+pub mod judge_event {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Event {
+        #[prost(message, tag = "1")]
+        Accepted(super::Accepted),
+        #[prost(message, tag = "2")]
+        Status(super::SubmissionStatus),
+        #[prost(message, tag = "3")]
+        Queued(super::Queued),
+    }
+}
+/// 等待提交, position 从 1 开始, estimated_wait 单位秒
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Queued {
+    #[prost(uint32, tag = "1")]
+    pub position: u32,
+    #[prost(uint64, tag = "2")]
+    pub estimated_wait: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Problem {
+    #[prost(string, tag = "1")]
+    pub problem_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub title: ::prost::alloc::string::String,
+    /// MS
+    #[prost(uint32, tag = "3")]
+    pub time_limit: u32,
+    /// MB
+    #[prost(uint32, tag = "4")]
+    pub memory_limit: u32,
+    #[prost(string, tag = "5")]
+    pub description: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub input_format: ::prost::alloc::string::String,
+    #[prost(string, tag = "7")]
+    pub output_format: ::prost::alloc::string::String,
+    #[prost(string, tag = "8")]
+    pub limit_and_hint: ::prost::alloc::string::String,
+    #[prost(string, repeated, tag = "9")]
+    pub examples_input: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "10")]
+    pub examples_output: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(map = "string, string", tag = "11")]
+    pub others: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+}
+/// Generated server implementations.
+pub mod remote_judge_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with RemoteJudgeServer.
+    #[async_trait]
+    pub trait RemoteJudge: Send + Sync + 'static {
+        /// Server streaming response type for the SubmitAndWatch method.
+        type SubmitAndWatchStream: futures_core::Stream<
+                Item = std::result::Result<super::JudgeEvent, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        /// 提交代码并推送测评状态, 直到测评结束
+        async fn submit_and_watch(
+            &self,
+            request: tonic::Request<super::SubmitRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::SubmitAndWatchStream>,
+            tonic::Status,
+        >;
+        async fn get_problem(
+            &self,
+            request: tonic::Request<super::ProblemRequest>,
+        ) -> std::result::Result<tonic::Response<super::Problem>, tonic::Status>;
+        /// Server streaming response type for the Track method.
+        type TrackStream: futures_core::Stream<
+                Item = std::result::Result<super::JudgeEvent, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        /// 继续推送已有提交的测评状态
+        async fn track(
+            &self,
+            request: tonic::Request<super::TrackRequest>,
+        ) -> std::result::Result<tonic::Response<Self::TrackStream>, tonic::Status>;
+        /// Server streaming response type for the Subscribe method.
+        type SubscribeStream: futures_core::Stream<
+                Item = std::result::Result<super::JudgeEvent, tonic::Status>,
+            >
+            + Send
+            + 'static;
+        /// 订阅已有的评测任务, 先推送最新状态, 不会重复 poll 远程 oj
+        async fn subscribe(
+            &self,
+            request: tonic::Request<super::SubscribeRequest>,
+        ) -> std::result::Result<tonic::Response<Self::SubscribeStream>, tonic::Status>;
+    }
+    #[derive(Debug)]
+    pub struct RemoteJudgeServer<T: RemoteJudge> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: RemoteJudge> RemoteJudgeServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for RemoteJudgeServer<T>
+    where
+        T: RemoteJudge,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/remote_judge.RemoteJudge/SubmitAndWatch" => {
+                    #[allow(non_camel_case_types)]
+                    struct SubmitAndWatchSvc<T: RemoteJudge>(pub Arc<T>);
+                    impl<
+                        T: RemoteJudge,
+                    > tonic::server::ServerStreamingService<super::SubmitRequest>
+                    for SubmitAndWatchSvc<T> {
+                        type Response = super::JudgeEvent;
+                        type ResponseStream = T::SubmitAndWatchStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubmitRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                (*inner).submit_and_watch(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SubmitAndWatchSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/remote_judge.RemoteJudge/GetProblem" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetProblemSvc<T: RemoteJudge>(pub Arc<T>);
+                    impl<
+                        T: RemoteJudge,
+                    > tonic::server::UnaryService<super::ProblemRequest>
+                    for GetProblemSvc<T> {
+                        type Response = super::Problem;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ProblemRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { (*inner).get_problem(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetProblemSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/remote_judge.RemoteJudge/Track" => {
+                    #[allow(non_camel_case_types)]
+                    struct TrackSvc<T: RemoteJudge>(pub Arc<T>);
+                    impl<
+                        T: RemoteJudge,
+                    > tonic::server::ServerStreamingService<super::TrackRequest>
+                    for TrackSvc<T> {
+                        type Response = super::JudgeEvent;
+                        type ResponseStream = T::TrackStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TrackRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { (*inner).track(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = TrackSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/remote_judge.RemoteJudge/Subscribe" => {
+                    #[allow(non_camel_case_types)]
+                    struct SubscribeSvc<T: RemoteJudge>(pub Arc<T>);
+                    impl<
+                        T: RemoteJudge,
+                    > tonic::server::ServerStreamingService<super::SubscribeRequest>
+                    for SubscribeSvc<T> {
+                        type Response = super::JudgeEvent;
+                        type ResponseStream = T::SubscribeStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubscribeRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { (*inner).subscribe(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SubscribeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        Ok(
+                            http::Response::builder()
+                                .status(200)
+                                .header("grpc-status", "12")
+                                .header("content-type", "application/grpc")
+                                .body(empty_body())
+                                .unwrap(),
+                        )
+                    })
+                }
+            }
+        }
+    }
+    impl<T: RemoteJudge> Clone for RemoteJudgeServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    impl<T: RemoteJudge> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(Arc::clone(&self.0))
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: RemoteJudge> tonic::server::NamedService for RemoteJudgeServer<T> {
+        const NAME: &'static str = "remote_judge.RemoteJudge";
+    }
+}
@@ -0,0 +1,141 @@
+// web-socket 协议
+// v1: 客户端直接发送 WsRequest, 回复为未标记类型的 json
+// v2: 客户端先发送 {"type": "hello", "version": 2} 协商版本, 之后所有回复都是带类型与序号的 Envelope
+use super::WsRequest;
+use crate::judger::provider::{Problem, SubmissionStatus};
+
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Hello { version: u32 },
+    Request(Box<WsRequest>),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Hello {
+        version: u32,
+    },
+    // 等待提交, position 从 1 开始, estimated_wait 单位秒
+    Queued {
+        position: usize,
+        estimated_wait: u64,
+    },
+    // 提交成功, handle 可用于之后的 track 请求
+    Accepted {
+        submission_id: String,
+        account: String,
+        handle: String,
+        // 评测任务的 id, 客户端断开后任务继续执行, 可通过 GET /jobs/{id} 查询结果
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        job_id: Option<String>,
+    },
+    Status(SubmissionStatus),
+    Problem(Problem),
+    Error {
+        message: String,
+        // 可以据此处理的错误才有, 例如远程 oj 熔断时为 circuit_open
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<String>,
+    },
+    // 请求处理结束, 之后不会再有该 request_id 的消息
+    Done,
+}
+
+impl ServerMessage {
+    // 转换为 v1 的回复格式, v1 中没有对应消息时返回 None
+    pub fn into_v1(self) -> Option<serde_json::Value> {
+        match self {
+            // v1 客户端不认识排队消息, 不发送
+            ServerMessage::Hello { .. } | ServerMessage::Queued { .. } | ServerMessage::Done => None,
+            ServerMessage::Accepted {
+                submission_id,
+                account,
+                handle,
+                job_id,
+            } => {
+                let mut value = serde_json::json!({
+                    "submissionId": submission_id,
+                    "account": account,
+                    "handle": handle,
+                });
+                if let Some(job_id) = job_id {
+                    value["jobId"] = job_id.into();
+                }
+                Some(value)
+            }
+            ServerMessage::Status(status) => serde_json::to_value(status).ok(),
+            ServerMessage::Problem(problem) => serde_json::to_value(problem).ok(),
+            ServerMessage::Error { message, code } => {
+                let mut value = serde_json::json!({ "error": message });
+                if let Some(code) = code {
+                    value["code"] = code.into();
+                }
+                Some(value)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Envelope {
+    pub version: u32,
+    pub seq: u64, // 同一连接内递增
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: ServerMessage,
+}
+
+// 由 rust 类型生成的 JSON Schema, 供客户端生成代码或校验消息
+pub fn schema() -> serde_json::Value {
+    serde_json::json!({
+        "version": PROTOCOL_VERSION,
+        "client": schema_for!(ClientMessage),
+        "server": schema_for!(Envelope),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_envelope() {
+        let envelope = Envelope {
+            version: PROTOCOL_VERSION,
+            seq: 3,
+            request_id: Some("r1".into()),
+            message: ServerMessage::Error {
+                message: "请求参数错误".into(),
+                code: None,
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&envelope).unwrap(),
+            serde_json::json!({
+                "version": 2,
+                "seq": 3,
+                "request_id": "r1",
+                "type": "error",
+                "message": "请求参数错误",
+            })
+        );
+        assert_eq!(ServerMessage::Done.into_v1(), None);
+
+        let msg: ClientMessage = serde_json::from_str(
+            r#"{"type": "request", "request_id": "r2", "remote_judge": "hdu", "request_type": "get_problem", "problem_id": "1000"}"#,
+        )
+        .unwrap();
+        assert!(
+            matches!(msg, ClientMessage::Request(req) if req.request_id.as_deref() == Some("r2"))
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// 提交队列: 每个 oj 同时进行的提交数受 submit_concurrency 限制, 其余请求排队
+// 排队顺序: 优先级 (contest > practice > rejudge), 同一优先级中正在提交数较少的客户端优先, 最后按到达顺序
+// 等待期间位置变化时发送 queued 消息
+use super::protocol::ServerMessage;
+use super::reply::Reply;
+use super::Priority;
+use crate::global::{
+    remote_judge_config, remote_judge_constant::names as remote_judge_names, server_config,
+};
+
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::watch;
+
+// 还没有统计数据时假设一次提交占用的时长, 秒
+const DEFAULT_SUBMIT_SECS: f64 = 10.0;
+
+struct Waiting {
+    id: u64,
+    client: String,
+    priority: Priority,
+}
+
+struct State {
+    limit: usize,
+    next_id: u64,
+    waiting: Vec<Waiting>, // 按到达顺序
+    running: HashMap<String, usize>,
+    running_total: usize,
+    avg_secs: f64, // 一次提交占用时长的滑动平均
+}
+
+impl State {
+    // 当前的排队顺序
+    fn order(&self) -> Vec<u64> {
+        let mut waiting: Vec<&Waiting> = self.waiting.iter().collect();
+        waiting.sort_by_key(|w| {
+            (
+                w.priority,
+                self.running.get(&w.client).copied().unwrap_or(0),
+                w.id,
+            )
+        });
+        waiting.iter().map(|w| w.id).collect()
+    }
+}
+
+struct Queue {
+    state: Mutex<State>,
+    changed: watch::Sender<u64>,
+}
+
+impl Queue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn notify(&self) {
+        self.changed.send_modify(|v| *v += 1);
+    }
+
+    // 轮到该请求时占用名额并返回 None, 否则返回排队位置 (从 1 开始) 与预计等待时间
+    fn try_enter(&self, id: u64, client: &str) -> Option<(usize, u64)> {
+        let mut state = self.lock();
+        let free = state.limit.saturating_sub(state.running_total);
+        let position = state.order().iter().position(|&i| i == id).unwrap_or(0);
+        if position < free {
+            state.waiting.retain(|w| w.id != id);
+            state.running_total += 1;
+            *state.running.entry(client.to_string()).or_default() += 1;
+            return None;
+        }
+        let rounds = (position + 1).div_ceil(state.limit.max(1));
+        Some((position + 1, (rounds as f64 * state.avg_secs).ceil() as u64))
+    }
+}
+
+fn queue(oj: &str) -> Arc<Queue> {
+    static QUEUES: OnceCell<Mutex<HashMap<String, Arc<Queue>>>> = OnceCell::new();
+    let mut queues = QUEUES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    queues
+        .entry(oj.to_string())
+        .or_insert_with(|| {
+            let limit = server_config()
+                .submit_concurrency
+                .get(oj)
+                .copied()
+                .or_else(|| remote_judge_config(oj).map(|c| c.accounts.len()))
+                .unwrap_or(1)
+                .max(1);
+            Arc::new(Queue {
+                state: Mutex::new(State {
+                    limit,
+                    next_id: 0,
+                    waiting: vec![],
+                    running: HashMap::new(),
+                    running_total: 0,
+                    avg_secs: DEFAULT_SUBMIT_SECS,
+                }),
+                changed: watch::channel(0).0,
+            })
+        })
+        .clone()
+}
+
+// 排队中或已占用的名额, drop 时离开队列或释放名额
+pub struct Ticket {
+    queue: Arc<Queue>,
+    id: u64,
+    client: String,
+    entered: Option<Instant>,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut state = self.queue.lock();
+        match self.entered {
+            None => state.waiting.retain(|w| w.id != self.id),
+            Some(entered) => {
+                state.running_total -= 1;
+                if let Some(n) = state.running.get_mut(&self.client) {
+                    *n -= 1;
+                    if *n == 0 {
+                        state.running.remove(&self.client);
+                    }
+                }
+                state.avg_secs = state.avg_secs * 0.8 + entered.elapsed().as_secs_f64() * 0.2;
+            }
+        }
+        drop(state);
+        self.queue.notify();
+    }
+}
+
+// 等待提交名额; gym 与 codeforces 共用账户, 也共用队列
+pub async fn enter(
+    oj: &str,
+    client: &str,
+    priority: Priority,
+    reply: &Reply,
+) -> anyhow::Result<Ticket> {
+    let oj = if oj == remote_judge_names::GYM {
+        remote_judge_names::CODEFORCES
+    } else {
+        oj
+    };
+    let queue = queue(oj);
+    // 先订阅再入队, 不会错过之后的变化
+    let mut changed = queue.changed.subscribe();
+    let id = {
+        let mut state = queue.lock();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.waiting.push(Waiting {
+            id,
+            client: client.to_string(),
+            priority,
+        });
+        id
+    };
+    let mut ticket = Ticket {
+        queue: queue.clone(),
+        id,
+        client: client.to_string(),
+        entered: None,
+    };
+
+    let mut last = None;
+    loop {
+        match queue.try_enter(id, client) {
+            None => {
+                ticket.entered = Some(Instant::now());
+                queue.notify();
+                return Ok(ticket);
+            }
+            Some((position, estimated_wait)) => {
+                if last != Some(position) {
+                    last = Some(position);
+                    reply.send(ServerMessage::Queued {
+                        position,
+                        estimated_wait,
+                    })?;
+                }
+            }
+        }
+        changed.changed().await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_order() {
+        let waiting = |id, client: &str, priority| Waiting {
+            id,
+            client: client.into(),
+            priority,
+        };
+        let state = State {
+            limit: 1,
+            next_id: 4,
+            waiting: vec![
+                waiting(1, "a", Priority::Practice),
+                waiting(2, "b", Priority::Practice),
+                waiting(3, "a", Priority::Contest),
+                waiting(4, "c", Priority::Rejudge),
+            ],
+            running: HashMap::from([("a".to_string(), 1)]),
+            running_total: 1,
+            avg_secs: DEFAULT_SUBMIT_SECS,
+        };
+        assert_eq!(state.order(), vec![3, 2, 1, 4]);
+    }
+}
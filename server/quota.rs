@@ -0,0 +1,103 @@
+// api key 的并发任务数与每日提交次数限制
+use super::auth::AuthContext;
+use super::store::job_store;
+use super::WsRequest;
+use crate::global::{server_config, task_constant::names as task_names};
+use crate::judger::utils::now_timestamp;
+
+use anyhow::anyhow;
+use once_cell::sync::OnceCell;
+use simple_log::log::error;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct KeyUsage {
+    in_flight: AtomicUsize,
+    daily: Mutex<(i64, u32)>, // (UTC 日期, 当日提交次数), 包括已预留但还未提交的次数
+}
+
+fn usage(name: &str) -> Arc<KeyUsage> {
+    static USAGE: OnceCell<Mutex<HashMap<String, Arc<KeyUsage>>>> = OnceCell::new();
+    USAGE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(name.to_string())
+        .or_default()
+        .clone()
+}
+
+// 持有期间计入 key 的并发任务数, drop 时释放
+// judge 请求同时预留一次当日提交次数, 提交成功后 confirm, 否则 drop 时退还
+pub struct Admission {
+    usage: Option<Arc<KeyUsage>>,
+    reserved: Option<i64>, // 预留提交次数的 UTC 日期
+}
+
+impl Admission {
+    pub fn confirm(&mut self) {
+        self.reserved = None;
+    }
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        if let Some(usage) = self.usage.take() {
+            usage.in_flight.fetch_sub(1, Ordering::SeqCst);
+            if let Some(day) = self.reserved.take() {
+                let mut daily = usage.daily.lock().unwrap_or_else(|e| e.into_inner());
+                if daily.0 == day && daily.1 > 0 {
+                    daily.1 -= 1;
+                }
+            }
+        }
+    }
+}
+
+pub fn admit(auth: &AuthContext, req: &WsRequest) -> anyhow::Result<Admission> {
+    let Some(key) = auth.key.as_ref().and_then(|name| server_config().api_key(name)) else {
+        return Ok(Admission {
+            usage: None,
+            reserved: None,
+        });
+    };
+    let usage = usage(&key.name);
+
+    let in_flight = usage.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+    // 先计入, 之后任何一步失败都由 Admission 的 drop 释放
+    let mut admission = Admission {
+        usage: Some(usage.clone()),
+        reserved: None,
+    };
+    if key.max_concurrent.map_or(false, |max| in_flight > max) {
+        return Err(anyhow!("{} 超过最大并发任务数", key.name));
+    }
+
+    if req.request_type == task_names::JUDGE {
+        if let Some(limit) = key.daily_submissions {
+            let today = now_timestamp().div_euclid(86400);
+            let mut daily = usage.daily.lock().unwrap_or_else(|e| e.into_inner());
+            if daily.0 != today {
+                // 服务启动后第一次使用时从 JobStore 中统计当日已成功的提交, 重启不会重置计数
+                let used = match daily.0 {
+                    0 => job_store()
+                        .submissions_since(&key.name, today * 86400)
+                        .unwrap_or_else(|e| {
+                            error!("统计 {} 当日提交次数失败: {}", key.name, e);
+                            0
+                        }),
+                    _ => 0,
+                };
+                *daily = (today, used);
+            }
+            if daily.1 >= limit {
+                return Err(anyhow!("{} 今日提交次数已用完", key.name));
+            }
+            daily.1 += 1;
+            admission.reserved = Some(today);
+        }
+    }
+    Ok(admission)
+}
@@ -0,0 +1,154 @@
+// 单个请求的回复通道: 同一连接上的多个请求并发执行, 回复统一交给连接的写循环发送
+// gRPC 请求的回复直接转发给对应的响应流; 关联了任务的请求, 回复同时记录在任务中, 客户端断开后继续执行
+use super::job::Job;
+use super::protocol::{Envelope, ServerMessage, PROTOCOL_VERSION};
+use crate::judger::utils::breaker::{CircuitOpen, CIRCUIT_OPEN};
+
+use anyhow::anyhow;
+use axum::extract::ws::Message;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
+
+// None 表示关闭连接
+pub type Outgoing = Option<Message>;
+
+// 一个连接的写端, 序号在连接内所有请求间共享
+#[derive(Clone)]
+pub struct Channel {
+    tx: UnboundedSender<Outgoing>,
+    seq: Arc<AtomicU64>,
+}
+
+impl Channel {
+    pub fn new(tx: UnboundedSender<Outgoing>) -> Self {
+        Self {
+            tx,
+            seq: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn reply(&self, request_id: Option<String>, version: u32) -> Reply {
+        Reply {
+            request_id,
+            version,
+            sink: Sink::Ws(self.clone()),
+            job: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Sink {
+    Ws(Channel),
+    Stream(UnboundedSender<ServerMessage>),
+    None, // 只记录在任务中
+}
+
+#[derive(Clone)]
+pub struct Reply {
+    request_id: Option<String>,
+    version: u32,
+    sink: Sink,
+    job: Option<Arc<Job>>,
+}
+
+impl Reply {
+    pub fn job(job: Arc<Job>) -> Self {
+        Self {
+            request_id: None,
+            version: PROTOCOL_VERSION,
+            sink: Sink::None,
+            job: Some(job),
+        }
+    }
+
+    pub fn stream(tx: UnboundedSender<ServerMessage>) -> Self {
+        Self {
+            request_id: None,
+            version: PROTOCOL_VERSION,
+            sink: Sink::Stream(tx),
+            job: None,
+        }
+    }
+
+    pub fn with_job(mut self, job: Arc<Job>) -> Self {
+        self.job = Some(job);
+        self
+    }
+
+    pub fn has_job(&self) -> bool {
+        self.job.is_some()
+    }
+
+    pub fn job_id(&self) -> Option<String> {
+        self.job.as_ref().map(|job| job.id.clone())
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // 客户端断开后, 关联了任务的请求继续执行, 否则返回错误以结束请求
+    pub fn send(&self, message: ServerMessage) -> anyhow::Result<()> {
+        match &self.job {
+            Some(job) => {
+                job.push(message.clone());
+                let _ = self.deliver(message);
+                Ok(())
+            }
+            None => self.deliver(message),
+        }
+    }
+
+    fn deliver(&self, message: ServerMessage) -> anyhow::Result<()> {
+        let channel = match &self.sink {
+            Sink::Ws(channel) => channel,
+            Sink::Stream(tx) => {
+                return tx.send(message).map_err(|_| anyhow!("请求已取消"));
+            }
+            Sink::None => return Ok(()),
+        };
+        let value = if self.version >= 2 {
+            serde_json::to_value(Envelope {
+                version: self.version,
+                seq: channel.seq.fetch_add(1, Ordering::SeqCst),
+                request_id: self.request_id.clone(),
+                message,
+            })?
+        } else {
+            let Some(mut value) = message.into_v1() else {
+                return Ok(());
+            };
+            // v1 的回复中附带请求的 request_id
+            if let (Some(id), Some(obj)) = (self.request_id.as_ref(), value.as_object_mut()) {
+                obj.insert("request_id".into(), id.clone().into());
+            }
+            value
+        };
+        channel
+            .tx
+            .send(Some(Message::Text(value.to_string())))
+            .map_err(|_| anyhow!("web-socket 连接已关闭"))
+    }
+
+    pub fn error(&self, e: anyhow::Error) {
+        let code = e
+            .downcast_ref::<CircuitOpen>()
+            .map(|_| CIRCUIT_OPEN.to_string());
+        let _ = self.send(ServerMessage::Error {
+            message: format!("{}", e),
+            code,
+        });
+    }
+
+    pub fn close(&self) {
+        if let Sink::Ws(channel) = &self.sink {
+            let _ = channel.tx.send(None);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// REST 接口: POST /jobs 创建任务, GET /jobs/{id} 查询最新状态, GET /jobs/{id}/events 以 SSE 推送任务消息
+// GET /jobs/{id}/deliveries 查询回调投递记录, GET /metrics 导出限流统计, GET /breakers 查询各 oj 的熔断状态
+use super::auth::{issue_ticket, AuthContext, TicketClaims, MAX_TICKET_TTL};
+use super::job::{jobs, Job};
+use super::reply::Reply;
+use super::server::execute;
+use super::store::job_store;
+use super::webhook;
+use super::WsRequest;
+use crate::global::{server_config, task_constant::names as task_names};
+use crate::judger::utils::{breaker, now_timestamp, rate_limit};
+
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Extension, Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+
+fn error_response(code: StatusCode, message: &str) -> Response {
+    (code, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+pub async fn create_job(
+    Extension(auth): Extension<AuthContext>,
+    Json(req): Json<WsRequest>,
+) -> Response {
+    if ![
+        task_names::JUDGE,
+        task_names::GET_PROBLEM,
+        task_names::TRACK,
+    ]
+    .contains(&req.request_type.as_str())
+    {
+        return error_response(StatusCode::BAD_REQUEST, "任务类型错误");
+    }
+    // 没有权限的请求不创建任务
+    if let Err(e) = auth.check(&req) {
+        return error_response(StatusCode::FORBIDDEN, &e.to_string());
+    }
+    if let Some(url) = req.callback_url.as_ref() {
+        if let Err(e) = webhook::resolve(url).await {
+            return error_response(StatusCode::BAD_REQUEST, &e.to_string());
+        }
+    }
+
+    let job = jobs().create(&req, auth.key.clone(), auth.owner());
+    let id = job.id.clone();
+    let auth = auth.with_client(format!("job:{}", id));
+    tokio::spawn(async move {
+        execute(&Reply::job(job), req, &auth).await;
+    });
+    (StatusCode::ACCEPTED, Json(serde_json::json!({ "id": id }))).into_response()
+}
+
+// 没有权限查看的任务与不存在的任务一样返回 404
+fn find_job(auth: &AuthContext, id: &str) -> Option<Arc<Job>> {
+    jobs().get(id).filter(|job| auth.can_view(&job.view()))
+}
+
+pub async fn get_job(Extension(auth): Extension<AuthContext>, Path(id): Path<String>) -> Response {
+    match find_job(&auth, &id) {
+        Some(job) => Json(job.view()).into_response(),
+        None => error_response(StatusCode::NOT_FOUND, "任务不存在"),
+    }
+}
+
+pub async fn job_events(
+    Extension(auth): Extension<AuthContext>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(job) = find_job(&auth, &id) else {
+        return error_response(StatusCode::NOT_FOUND, "任务不存在");
+    };
+
+    // 先补发已有的消息, 再推送后续消息; 两者在同一把锁内取得, 不会重复或遗漏
+    // 任务结束后发送端被丢弃, 流随之结束
+    let (history, rx) = job.subscribe();
+    let rx = rx.unwrap_or_else(|| broadcast::channel(1).1);
+    let stream = tokio_stream::iter(history).chain(BroadcastStream::new(rx).filter_map(|e| e.ok()));
+    let stream = stream.map(|envelope| {
+        Event::default()
+            .id(envelope.seq.to_string())
+            .json_data(&envelope)
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+// 任务的回调投递记录
+pub async fn job_deliveries(
+    Extension(auth): Extension<AuthContext>,
+    Path(id): Path<String>,
+) -> Response {
+    if find_job(&auth, &id).is_none() {
+        return error_response(StatusCode::NOT_FOUND, "任务不存在");
+    }
+    match job_store().deliveries(&id) {
+        Ok(deliveries) => Json(deliveries).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+// prometheus 抓取的访问远程 oj 的限流统计
+pub async fn metrics() -> Response {
+    (
+        [("Content-Type", "text/plain; version=0.0.4")],
+        rate_limit::metrics(),
+    )
+        .into_response()
+}
+
+pub async fn breakers() -> Response {
+    Json(breaker::views()).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TicketRequest {
+    pub ojs: Vec<String>,
+    #[serde(default = "default_ticket_ttl")]
+    pub ttl: u64, // 秒, 最长 MAX_TICKET_TTL
+}
+
+fn default_ticket_ttl() -> u64 {
+    300
+}
+
+// 由持有 ACCESS_TOKEN 或 api key 的后端为浏览器签发 ticket
+pub async fn create_ticket(
+    Extension(auth): Extension<AuthContext>,
+    Json(body): Json<TicketRequest>,
+) -> Response {
+    if auth.is_ticket() {
+        return error_response(StatusCode::FORBIDDEN, "没有权限");
+    }
+    let Some(secret) = server_config().ticket_secret.as_ref() else {
+        return error_response(StatusCode::BAD_REQUEST, "未配置 ticket_secret");
+    };
+    // ticket 的权限不能超过签发它的 api key
+    let ojs = body
+        .ojs
+        .into_iter()
+        .filter(|oj| auth.ojs.as_ref().map_or(true, |ojs| ojs.contains(oj)))
+        .collect();
+    let claims = TicketClaims {
+        exp: now_timestamp() + std::cmp::min(body.ttl, MAX_TICKET_TTL) as i64,
+        ojs,
+        key: auth.key.clone(),
+    };
+    Json(serde_json::json!({
+        "ticket": issue_ticket(secret, &claims),
+        "expires_at": claims.exp,
+    }))
+    .into_response()
+}
@@ -0,0 +1,337 @@
+// 统一的 poll 调度: 每个 oj 一个队列, 持有全部待测评的提交, 按 poll_rates 限制请求频率
+// 同一提交只 poll 一次, 同一账户的提交通过 poll_many 一次请求更新, 状态变化通过 channel 推送给所有订阅者
+// 优先 poll 新提交, 新提交的状态变化最快; 远程 oj 熔断期间暂停 poll
+use crate::global::{remote_judge_constant::names as remote_judge_names, server_config};
+use crate::judger::{
+    self,
+    provider::{Provider, SubmissionHandle, SubmissionStatus},
+    utils::breaker::{self, CircuitOpen},
+};
+
+use anyhow::anyhow;
+use once_cell::sync::OnceCell;
+use simple_log::log::error;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Instant;
+
+// 未在 poll_rates 中配置的 oj 每秒最多 poll 的次数
+pub const DEFAULT_POLL_RATE: f64 = 2.0;
+
+pub type StatusReceiver = mpsc::UnboundedReceiver<anyhow::Result<SubmissionStatus>>;
+type StatusSender = mpsc::UnboundedSender<anyhow::Result<SubmissionStatus>>;
+
+// 调度器持有的账户, 同一账户的提交共用, 不再有提交时释放
+enum Poller {
+    Codeforces(judger::Codeforces),
+    Hdu(judger::Hdu),
+    Atcoder(judger::Atcoder),
+}
+
+impl Poller {
+    fn from_handle(handle: &SubmissionHandle) -> anyhow::Result<Self> {
+        Ok(match handle.oj.as_str() {
+            remote_judge_names::CODEFORCES | remote_judge_names::GYM => {
+                Self::Codeforces(judger::Codeforces::from_handle(handle)?)
+            }
+            remote_judge_names::HDU => Self::Hdu(judger::Hdu::from_handle(handle)?),
+            remote_judge_names::ATCODER => Self::Atcoder(judger::Atcoder::from_handle(handle)?),
+            _ => return Err(anyhow!("不支持 该 OJ 测评")),
+        })
+    }
+
+    async fn poll_many(
+        &self,
+        handles: &[SubmissionHandle],
+    ) -> anyhow::Result<HashMap<String, SubmissionStatus>> {
+        match self {
+            Self::Codeforces(p) => p.poll_many(handles).await,
+            Self::Hdu(p) => p.poll_many(handles).await,
+            Self::Atcoder(p) => p.poll_many(handles).await,
+        }
+    }
+}
+
+struct Pending {
+    handle: SubmissionHandle,
+    subscribers: Vec<StatusSender>,
+    created_at: Instant,
+    due: Instant,
+    polling: bool,
+    polls: usize,
+    wait: u32,
+    latest: Option<SubmissionStatus>, // 发给之后加入的订阅者
+    last_error: Option<String>,       // 最近一次 poll 失败的原因, 超过最大重试次数时一并返回
+}
+
+impl Pending {
+    fn same(&self, handle: &SubmissionHandle) -> bool {
+        self.handle.oj == handle.oj
+            && self.handle.account == handle.account
+            && self.handle.remote_id == handle.remote_id
+    }
+
+    fn publish(&mut self, result: anyhow::Result<SubmissionStatus>) {
+        match result {
+            Ok(status) => self
+                .subscribers
+                .retain(|tx| tx.send(Ok(status.clone())).is_ok()),
+            Err(e) => {
+                let message = e.to_string();
+                self.subscribers
+                    .retain(|tx| tx.send(Err(anyhow!(message.clone()))).is_ok())
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: Mutex<Vec<Pending>>,
+    pollers: Mutex<HashMap<String, Arc<Poller>>>,
+    notify: Notify,
+}
+
+impl Queue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Pending>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn poller(&self, handle: &SubmissionHandle) -> anyhow::Result<Arc<Poller>> {
+        let mut pollers = self.pollers.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(poller) = pollers.get(&handle.account) {
+            return Ok(poller.clone());
+        }
+        let poller = Arc::new(Poller::from_handle(handle)?);
+        pollers.insert(handle.account.clone(), poller.clone());
+        Ok(poller)
+    }
+
+    // 清理已结束或没有订阅者的提交, 并释放不再使用的账户
+    fn retain(&self, pending: &mut Vec<Pending>) {
+        pending.retain(|p| p.subscribers.iter().any(|tx| !tx.is_closed()));
+        self.pollers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|account, _| pending.iter().any(|p| &p.handle.account == account));
+    }
+
+    // 选出到期的提交中最新的一个, 连同同一账户的其他提交一起 poll, 附带各提交是否已到期
+    // 没有到期的提交时返回最早的到期时间
+    fn next(&self) -> Result<Vec<(SubmissionHandle, bool)>, Option<Instant>> {
+        let mut pending = self.lock();
+        self.retain(&mut pending);
+        let now = Instant::now();
+        let account = pending
+            .iter()
+            .filter(|p| !p.polling && p.due <= now)
+            .max_by_key(|p| p.created_at)
+            .map(|p| p.handle.account.clone());
+        let Some(account) = account else {
+            return Err(pending.iter().filter(|p| !p.polling).map(|p| p.due).min());
+        };
+        Ok(pending
+            .iter_mut()
+            .filter(|p| !p.polling && p.handle.account == account)
+            .map(|p| {
+                p.polling = true;
+                (p.handle.clone(), p.due <= now)
+            })
+            .collect())
+    }
+
+    // 与原来每个连接各自 poll 的退避方式相同: 状态变化后回到 wait_base, 否则逐步增加到 max_wait_time
+    // 未到期的提交顺带 poll 时, 状态没有变化则保持原来的计划
+    // poll 失败时 result 为 Err, 按状态没有变化处理
+    fn complete(
+        &self,
+        handle: &SubmissionHandle,
+        result: Result<Option<SubmissionStatus>, &str>,
+        due: bool,
+    ) {
+        let config = server_config();
+        let mut pending = self.lock();
+        let Some(index) = pending.iter().position(|p| p.same(handle)) else {
+            return;
+        };
+        let p = &mut pending[index];
+        p.polling = false;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                p.last_error = Some(e.to_string());
+                None
+            }
+        };
+
+        let changed =
+            result.filter(|status| p.latest.as_ref().map(|s| &s.info) != Some(&status.info));
+        if changed.is_none() && !due {
+            return;
+        }
+        if due {
+            p.polls += 1;
+        }
+
+        let mut finished = false;
+        match changed {
+            Some(status) => {
+                finished = status.is_over;
+                p.latest = Some(status.clone());
+                p.last_error = None;
+                p.wait = config.wait_base;
+                p.publish(Ok(status));
+            }
+            None => p.wait = std::cmp::min(p.wait + config.wait_incr, config.max_wait_time),
+        }
+        if !finished && p.polls >= config.max_poll_times {
+            p.publish(Err(match p.last_error.as_ref() {
+                Some(e) => anyhow!("超过最大重试次数， 最后一次错误: {}", e),
+                None => anyhow!("超过最大重试次数， 请查看远程测评网站是否不可访问"),
+            }));
+            finished = true;
+        }
+        if finished {
+            pending.remove(index);
+        } else {
+            p.due = Instant::now() + Duration::from_secs(p.wait as u64);
+        }
+        self.retain(&mut pending);
+    }
+
+    // 熔断导致没有 poll 成功, 不计入 poll 次数, 恢复后照原计划继续
+    fn release(&self, handle: &SubmissionHandle) {
+        if let Some(p) = self.lock().iter_mut().find(|p| p.same(handle)) {
+            p.polling = false;
+        }
+    }
+
+    fn fail(&self, handle: &SubmissionHandle, e: anyhow::Error) {
+        let mut pending = self.lock();
+        if let Some(index) = pending.iter().position(|p| p.same(handle)) {
+            pending[index].publish(Err(e));
+            pending.remove(index);
+        }
+        self.retain(&mut pending);
+    }
+
+    async fn run(self: Arc<Self>, oj: String, rate: f64) {
+        let interval = Duration::from_secs_f64(1.0 / rate.max(0.01));
+        loop {
+            if let Err(e) = breaker::check(&oj) {
+                tokio::time::sleep(Duration::from_secs(e.retry_after)).await;
+                continue;
+            }
+            match self.next() {
+                Ok(batch) => {
+                    let queue = self.clone();
+                    tokio::spawn(async move {
+                        let handles: Vec<SubmissionHandle> =
+                            batch.iter().map(|(h, _)| h.clone()).collect();
+                        match queue.poller(&handles[0]) {
+                            Ok(poller) => match poller.poll_many(&handles).await {
+                                Err(e) if e.is::<CircuitOpen>() => {
+                                    handles.iter().for_each(|handle| queue.release(handle))
+                                }
+                                Ok(mut res) => {
+                                    for (handle, due) in batch {
+                                        queue.complete(
+                                            &handle,
+                                            Ok(res.remove(&handle.remote_id)),
+                                            due,
+                                        );
+                                    }
+                                }
+                                // 网络错误与 5xx 已经在请求时计入熔断器, 其他错误 (例如页面无法解析) 在这里计入
+                                Err(e) => {
+                                    let handle = &handles[0];
+                                    error!(
+                                        "poll {} 账户 {} 的提交失败: {}",
+                                        handle.oj, handle.account, e
+                                    );
+                                    if !e.is::<reqwest::Error>() {
+                                        breaker::failure(&handle.oj, &e);
+                                    }
+                                    let message = e.to_string();
+                                    for (handle, due) in batch {
+                                        queue.complete(&handle, Err(&message), due);
+                                    }
+                                }
+                            },
+                            Err(e) => {
+                                let message = e.to_string();
+                                for handle in handles {
+                                    queue.fail(&handle, anyhow!(message.clone()));
+                                }
+                            }
+                        }
+                        // 所有提交都在 poll 时调度循环在等待, poll 结束后需要唤醒
+                        queue.notify.notify_one();
+                    });
+                    tokio::time::sleep(interval).await;
+                }
+                Err(Some(due)) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(due) => {}
+                        _ = self.notify.notified() => {}
+                    }
+                }
+                Err(None) => self.notify.notified().await,
+            }
+        }
+    }
+}
+
+fn queue(oj: &str) -> Arc<Queue> {
+    static QUEUES: OnceCell<Mutex<HashMap<String, Arc<Queue>>>> = OnceCell::new();
+    let mut queues = QUEUES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(queue) = queues.get(oj) {
+        return queue.clone();
+    }
+    let queue = Arc::new(Queue::default());
+    let rate = server_config()
+        .poll_rates
+        .get(oj)
+        .copied()
+        .unwrap_or(DEFAULT_POLL_RATE);
+    tokio::spawn(queue.clone().run(oj.to_string(), rate));
+    queues.insert(oj.to_string(), queue.clone());
+    queue
+}
+
+// 订阅提交的状态变化, 测评结束或超过最大 poll 次数后 channel 关闭; 丢弃接收端即取消订阅
+pub fn watch(handle: &SubmissionHandle) -> StatusReceiver {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let queue = queue(&handle.oj);
+    let mut pending = queue.lock();
+    match pending.iter_mut().find(|p| p.same(handle)) {
+        Some(p) => {
+            if let Some(status) = p.latest.as_ref() {
+                let _ = tx.send(Ok(status.clone()));
+            }
+            p.subscribers.push(tx)
+        }
+        None => {
+            let now = Instant::now();
+            pending.push(Pending {
+                handle: handle.clone(),
+                subscribers: vec![tx],
+                created_at: now,
+                due: now,
+                polling: false,
+                polls: 0,
+                wait: server_config().wait_base,
+                latest: None,
+                last_error: None,
+            });
+        }
+    }
+    drop(pending);
+    queue.notify.notify_one();
+    rx
+}
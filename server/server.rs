@@ -1,20 +1,30 @@
 use crate::{
-    global::{self, remote_judge_constant::names as remote_judge_names},
-    judger,
+    global::{
+        self, remote_judge_constant::names as remote_judge_names,
+        task_constant::names as task_names,
+    },
+    judger::{self, utils::breaker},
 };
 
 use anyhow::anyhow;
 use axum::{
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::ws::{Message, WebSocketUpgrade},
     middleware::{self, Next},
     response::{IntoResponse, Response},
-    routing::get,
-    Router,
+    routing::{get, post},
+    Extension, Json, Router,
 };
 use hyper::Request;
-use simple_log::log::info;
+use simple_log::log::{error, info};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
 
-use super::{task, WsRequest};
+use super::auth::{self, AuthContext};
+use super::job::jobs;
+use super::store::job_store;
+use super::protocol::{self, ClientMessage, ServerMessage, PROTOCOL_VERSION};
+use super::reply::{Channel, Outgoing, Reply};
+use super::{queue, quota, rest, task, webhook, WsRequest};
 
 pub async fn make_ws_server() {
     let config = global::server_config();
@@ -23,6 +33,14 @@ pub async fn make_ws_server() {
     info!("web-socket服务: {}", ws_addr);
     let router = Router::new()
         .route("/entry", get(web_socket_handler))
+        .route("/schema", get(schema_handler))
+        .route("/jobs", post(rest::create_job))
+        .route("/jobs/:id", get(rest::get_job))
+        .route("/jobs/:id/events", get(rest::job_events))
+        .route("/jobs/:id/deliveries", get(rest::job_deliveries))
+        .route("/tickets", post(rest::create_ticket))
+        .route("/metrics", get(rest::metrics))
+        .route("/breakers", get(rest::breakers))
         .route_layer(middleware::from_fn(check_access_token));
 
     axum::Server::bind(&ws_addr.parse().unwrap())
@@ -31,55 +49,252 @@ pub async fn make_ws_server() {
         .unwrap();
 }
 
-async fn check_access_token<B>(req: Request<B>, next: Next<B>) -> Response {
-    let token = global::server_config().access_token.as_ref();
-    if token.is_none() {
+async fn check_access_token<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let token = req
+        .headers()
+        .get("ACCESS_TOKEN")
+        .and_then(|v| v.to_str().ok());
+    if let Some(ctx) = auth::authenticate(token) {
+        req.extensions_mut().insert(ctx);
         return next.run(req).await;
     }
-    if let Some(token_header) = req.headers().get("ACCESS_TOKEN") {
-        if let Ok(access_token) = token_header.to_str() {
-            if access_token == token.unwrap() {
-                return next.run(req).await;
+    if let (Some(secret), (Some(ticket), protocol)) = (
+        global::server_config().ticket_secret.as_ref(),
+        find_ticket(&req),
+    ) {
+        let ctx = auth::verify_ticket(secret, &ticket)
+            .and_then(|claims| AuthContext::from_ticket(claims, protocol))
+            .map(|ctx| ctx.with_client(format!("ticket:{}", auth::ticket_id(&ticket))));
+        return match ctx {
+            Ok(ctx) => {
+                req.extensions_mut().insert(ctx);
+                next.run(req).await
             }
-        }
+            Err(e) => serde_json::json!({"error": format!("{}", e)})
+                .to_string()
+                .into_response(),
+        };
     }
     serde_json::json!({"error": "没有权限"})
         .to_string()
         .into_response()
 }
 
-async fn web_socket_handler(ws: WebSocketUpgrade) -> Response {
+// 从 ?ticket= 或 Sec-WebSocket-Protocol 中取出 ticket, 后者同时返回需要回显的子协议
+fn find_ticket<B>(req: &Request<B>) -> (Option<String>, Option<String>) {
+    let from_query = req.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|kv| kv.strip_prefix("ticket="))
+            .and_then(|v| urlencoding::decode(v).ok())
+            .map(|v| v.into_owned())
+    });
+    if from_query.is_some() {
+        return (from_query, None);
+    }
+
+    let protocols = req
+        .headers()
+        .get("Sec-WebSocket-Protocol")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    for protocol in protocols.split(',').map(|p| p.trim()) {
+        if let Some(ticket) = protocol.strip_prefix(auth::TICKET_PROTOCOL_PREFIX) {
+            return (Some(ticket.to_string()), Some(protocol.to_string()));
+        }
+    }
+    (None, None)
+}
+
+async fn schema_handler() -> Json<serde_json::Value> {
+    Json(protocol::schema())
+}
+
+async fn web_socket_handler(
+    ws: WebSocketUpgrade,
+    Extension(auth): Extension<AuthContext>,
+) -> Response {
+    // 没有 api key 与 ticket 的连接各自公平排队
+    static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
+    let auth = auth.with_client(format!(
+        "ws:{}",
+        NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed)
+    ));
+    // 浏览器要求服务端回显所选的子协议, 否则会断开连接
+    let ws = match auth.protocol.clone() {
+        Some(protocol) => ws.protocols([protocol]),
+        None => ws,
+    };
     ws.on_upgrade(|mut socket| async move {
-        let Some(Ok(msg)) = socket.recv().await else {
-            let _ = socket.send( Message::Text(serde_json::json!({"error": "获取 web-socket数据失败"}).to_string()
-        )).await;
-            return ;
-        };
-        
-        let req = match serde_json::from_slice::<WsRequest>(msg.into_data().as_slice()) {
-            Ok(_conf) => _conf,
-            Err(_) => {
-                let _ = socket.send( Message::Text(serde_json::json!({"error": "请求数据错误"}).to_string())).await;
-                return;
+        // 各请求的回复汇总到 rx, 由当前循环统一写回连接
+        let (tx, mut rx) = mpsc::unbounded_channel::<Outgoing>();
+        let channel = Channel::new(tx);
+        // 未发送 hello 的连接按 v1 处理
+        let mut version = 1;
+        loop {
+            tokio::select! {
+                msg = socket.recv() => {
+                    let data = match msg {
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => continue,
+                        Some(Ok(msg)) => msg.into_data(),
+                    };
+                    accept(data, &channel, &mut version, &auth);
+                }
+                out = rx.recv() => match out {
+                    Some(Some(msg)) => {
+                        if socket.send(msg).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ => break,
+                }
             }
-        };
-
-        info!("{:?}", req);
-        
-        if let Err(e) = dispatch(&mut socket, req).await {
-            let _ = socket.send( Message::Text(serde_json::json!({"error": format!("{}", e)}).to_string())).await;
         }
+        let _ = socket.close().await;
     })
 }
 
-async fn dispatch(ws: &mut WebSocket, req: WsRequest) -> anyhow::Result<()> {
+// 解析请求并在独立的任务中执行, 不阻塞同一连接上的其他请求
+fn accept(data: Vec<u8>, channel: &Channel, version: &mut u32, auth: &AuthContext) {
+    let msg = serde_json::from_slice::<ClientMessage>(data.as_slice()).or_else(|e| {
+        if *version < PROTOCOL_VERSION {
+            serde_json::from_slice::<WsRequest>(data.as_slice()).map(|req| ClientMessage::Request(Box::new(req)))
+        } else {
+            Err(e)
+        }
+    });
+
+    let req = match msg {
+        Ok(ClientMessage::Request(req)) => *req,
+        Ok(ClientMessage::Hello { version: v }) => {
+            *version = v.clamp(1, PROTOCOL_VERSION);
+            let _ = channel.reply(None, *version).send(ServerMessage::Hello {
+                version: *version,
+            });
+            return;
+        }
+        Err(_) => {
+            // 尽量取出 request_id, 让客户端知道是哪个请求出错
+            let request_id = serde_json::from_slice::<serde_json::Value>(data.as_slice())
+                .ok()
+                .and_then(|v| v.get("request_id")?.as_str().map(String::from));
+            let reply = channel.reply(request_id, *version);
+            reply.error(anyhow!("请求数据错误"));
+            if reply.version() < 2 && reply.request_id().is_none() {
+                reply.close();
+            }
+            return;
+        }
+    };
+
+    let reply = channel.reply(req.request_id.clone(), *version);
+    let auth = auth.clone();
+    tokio::spawn(async move {
+        execute(&reply, req, &auth).await;
+        // v1 中没有 request_id 的请求沿用一个连接一个请求的方式
+        if reply.version() < 2 && reply.request_id().is_none() {
+            reply.close();
+        }
+    });
+}
+
+// 执行请求, 结束时发送 done 或 error
+// judge / track 请求总是关联一个任务, 客户端断开后继续 poll, 结果可通过 GET /jobs/{id} 查询
+pub async fn execute(reply: &Reply, req: WsRequest, auth: &AuthContext) {
+    // 回调地址不合法时不创建任务
+    if let Some(url) = req.callback_url.as_ref() {
+        if let Err(e) = webhook::resolve(url).await {
+            reply.error(e);
+            return;
+        }
+    }
+    let detached;
+    let reply = if !reply.has_job()
+        && [task_names::JUDGE, task_names::TRACK].contains(&req.request_type.as_str())
+    {
+        detached = reply.clone().with_job(jobs().create(&req, auth.key.clone(), auth.owner()));
+        &detached
+    } else {
+        reply
+    };
+    finish(reply, dispatch(reply, req, auth).await);
+}
+
+fn finish(reply: &Reply, result: anyhow::Result<()>) {
+    match result {
+        Ok(_) => {
+            let _ = reply.send(ServerMessage::Done);
+        }
+        Err(e) => reply.error(e),
+    }
+}
+
+// 服务重启后恢复未结束的任务: 已提交的用提交时的账户继续 poll, 结果照常推送给任务的订阅者与回调地址
+// 还没有拿到 handle 的任务无法确定是否已经提交, 不重复提交, 直接标记为失败
+pub async fn recover_jobs() {
+    let records = match job_store().unfinished() {
+        Ok(records) => records,
+        Err(e) => {
+            error!("读取未结束的任务失败: {}", e);
+            return;
+        }
+    };
+    for (view, req, history) in records {
+        info!("恢复任务 {} {}", view.id, view.remote_judge);
+        let handle = view.handle.clone();
+        let reply = Reply::job(jobs().resume(view, &req, history));
+        tokio::spawn(async move {
+            let result = match handle {
+                Some(token) => match auth::verify_handle(auth::handle_secret(), &token) {
+                    Ok(handle) => task::track(&reply, handle).await,
+                    Err(e) => Err(e),
+                },
+                None => Err(anyhow!("服务重启, 任务中断")),
+            };
+            finish(&reply, result);
+        });
+    }
+}
+
+async fn dispatch(reply: &Reply, req: WsRequest, auth: &AuthContext) -> anyhow::Result<()> {
+    info!("[{}] {:?}", auth.name(), req);
+    auth.check(&req)?;
+    let mut admission = quota::admit(auth, &req)?;
+
+    if req.request_type == task_names::SUBSCRIBE {
+        // 只能订阅自己创建的, 有权限的 oj 上的任务
+        let job = req
+            .job_id
+            .as_ref()
+            .and_then(|id| jobs().get(id))
+            .filter(|job| {
+                let view = job.view();
+                view.remote_judge == req.remote_judge && auth.can_view(&view)
+            })
+            .ok_or(anyhow!("任务不存在"))?;
+        return task::watch(&job, reply).await;
+    }
+
+    if req.request_type == task_names::TRACK {
+        // 继续 poll 已有的提交, scheduler 使用提交时的账户
+        return task::track(reply, task::track_handle(&req)?).await;
+    }
+
+    // 远程 oj 熔断中, 新任务直接拒绝, 不再登录与重试
+    breaker::check(&req.remote_judge)?;
+    // 先排队等待提交名额, 轮到后再占用账户
+    let ticket = match req.request_type.as_str() {
+        task_names::JUDGE => Some(queue::enter(&req.remote_judge, auth.client(), req.priority, reply).await?),
+        _ => None,
+    };
     match req.remote_judge.as_str() {
         remote_judge_names::CODEFORCES => {
-            task::run(&judger::Codeforces::new(false).await?, ws, req).await
+            task::run(&judger::Codeforces::new(false).await?, reply, req, ticket, &mut admission).await
         }
-        remote_judge_names::GYM => task::run(&judger::Codeforces::new(true).await?, ws, req).await,
-        remote_judge_names::HDU => task::run(&judger::Hdu::new().await?, ws, req).await,
-        remote_judge_names::ATCODER => task::run(&judger::Atcoder::new().await?, ws, req).await,
+        remote_judge_names::GYM => task::run(&judger::Codeforces::new(true).await?, reply, req, ticket, &mut admission).await,
+        remote_judge_names::HDU => task::run(&judger::Hdu::new().await?, reply, req, ticket, &mut admission).await,
+        remote_judge_names::ATCODER => task::run(&judger::Atcoder::new().await?, reply, req, ticket, &mut admission).await,
         _ => Err(anyhow!("请求类型错误")),
     }
 }
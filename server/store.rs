@@ -0,0 +1,283 @@
+// 任务持久化: 记录请求, 每一条消息 (包括提交句柄与各次状态变化) 以及最终状态, 客户端断开或服务重启后仍可按任务 id 查询
+use super::job::{JobState, JobView};
+use super::protocol::Envelope;
+use super::webhook::Delivery;
+use super::WsRequest;
+use crate::global::{server_config, task_constant::names as task_names};
+
+use once_cell::sync::OnceCell;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub trait JobStore: Send + Sync {
+    fn insert(&self, view: &JobView, req: &WsRequest) -> anyhow::Result<()>;
+    // 追加一条消息并更新任务概况
+    fn append(&self, view: &JobView, event: &Envelope) -> anyhow::Result<()>;
+    fn load(&self, id: &str) -> anyhow::Result<Option<(JobView, Vec<Envelope>)>>;
+    // 服务重启时仍在执行的任务
+    fn unfinished(&self) -> anyhow::Result<Vec<(JobView, WsRequest, Vec<Envelope>)>>;
+    // api key 在 since 之后创建且已经提交成功的 judge 任务数
+    fn submissions_since(&self, key: &str, since: i64) -> anyhow::Result<u32>;
+    fn log_delivery(&self, delivery: &Delivery) -> anyhow::Result<()>;
+    fn deliveries(&self, job_id: &str) -> anyhow::Result<Vec<Delivery>>;
+}
+
+pub struct SqliteJobStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteJobStore {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            CREATE TABLE IF NOT EXISTS jobs (
+                id TEXT PRIMARY KEY,
+                key TEXT,
+                state TEXT NOT NULL,
+                request TEXT NOT NULL,
+                view TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS jobs_state ON jobs (state);
+            CREATE TABLE IF NOT EXISTS job_events (
+                job_id TEXT NOT NULL,
+                seq INTEGER NOT NULL,
+                event TEXT NOT NULL,
+                PRIMARY KEY (job_id, seq)
+            );
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                job_id TEXT NOT NULL,
+                url TEXT NOT NULL,
+                event TEXT NOT NULL,
+                attempt INTEGER NOT NULL,
+                status INTEGER,
+                error TEXT,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS webhook_deliveries_job ON webhook_deliveries (job_id);",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn state_str(state: JobState) -> String {
+    serde_json::to_value(state)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+impl JobStore for SqliteJobStore {
+    fn insert(&self, view: &JobView, req: &WsRequest) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT INTO jobs (id, key, state, request, view, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                view.id,
+                view.key,
+                state_str(view.state),
+                serde_json::to_string(req)?,
+                serde_json::to_string(view)?,
+                view.created_at,
+                view.updated_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn append(&self, view: &JobView, event: &Envelope) -> anyhow::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO job_events (job_id, seq, event) VALUES (?1, ?2, ?3)",
+            params![view.id, event.seq as i64, serde_json::to_string(event)?],
+        )?;
+        tx.execute(
+            "UPDATE jobs SET state = ?2, view = ?3, updated_at = ?4 WHERE id = ?1",
+            params![
+                view.id,
+                state_str(view.state),
+                serde_json::to_string(view)?,
+                view.updated_at,
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn load(&self, id: &str) -> anyhow::Result<Option<(JobView, Vec<Envelope>)>> {
+        let conn = self.conn();
+        let view: Option<String> = conn
+            .query_row("SELECT view FROM jobs WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        let Some(view) = view else {
+            return Ok(None);
+        };
+
+        let mut stmt =
+            conn.prepare("SELECT event FROM job_events WHERE job_id = ?1 ORDER BY seq")?;
+        let events = stmt
+            .query_map([id], |row| row.get::<_, String>(0))?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| serde_json::from_str(&e).ok())
+            .collect();
+        Ok(Some((serde_json::from_str(&view)?, events)))
+    }
+
+    fn unfinished(&self) -> anyhow::Result<Vec<(JobView, WsRequest, Vec<Envelope>)>> {
+        let rows: Vec<(String, String)> = {
+            let conn = self.conn();
+            let mut stmt =
+                conn.prepare("SELECT id, request FROM jobs WHERE state = ?1 ORDER BY created_at")?;
+            let rows = stmt
+                .query_map([state_str(JobState::Running)], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<Result<_, _>>()?;
+            rows
+        };
+        let mut jobs = vec![];
+        for (id, req) in rows {
+            if let Some((view, history)) = self.load(&id)? {
+                jobs.push((view, serde_json::from_str(&req)?, history));
+            }
+        }
+        Ok(jobs)
+    }
+
+    fn submissions_since(&self, key: &str, since: i64) -> anyhow::Result<u32> {
+        Ok(self.conn().query_row(
+            "SELECT COUNT(*) FROM jobs WHERE key = ?1 AND created_at >= ?2
+            AND json_extract(view, '$.request_type') = ?3
+            AND json_extract(view, '$.submission_id') IS NOT NULL",
+            params![key, since, task_names::JUDGE],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn log_delivery(&self, delivery: &Delivery) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT INTO webhook_deliveries (job_id, url, event, attempt, status, error, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                delivery.job_id,
+                delivery.url,
+                delivery.event,
+                delivery.attempt,
+                delivery.status,
+                delivery.error,
+                delivery.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn deliveries(&self, job_id: &str) -> anyhow::Result<Vec<Delivery>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT url, event, attempt, status, error, created_at FROM webhook_deliveries
+            WHERE job_id = ?1 ORDER BY rowid",
+        )?;
+        let deliveries = stmt
+            .query_map([job_id], |row| {
+                Ok(Delivery {
+                    job_id: job_id.into(),
+                    url: row.get(0)?,
+                    event: row.get(1)?,
+                    attempt: row.get(2)?,
+                    status: row.get(3)?,
+                    error: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(deliveries)
+    }
+}
+
+// 其他存储只需实现 JobStore 并在此处初始化
+static JOB_STORE: OnceCell<Box<dyn JobStore>> = OnceCell::new();
+
+pub fn job_store() -> &'static dyn JobStore {
+    JOB_STORE
+        .get_or_init(|| {
+            Box::new(SqliteJobStore::open(&server_config().job_db).expect("打开任务数据库失败"))
+        })
+        .as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::server::protocol::ServerMessage;
+
+    #[test]
+    fn test_sqlite_store() {
+        let store = SqliteJobStore::open_in_memory().unwrap();
+        let req = WsRequest {
+            remote_judge: "hdu".into(),
+            request_type: "judge".into(),
+            ..Default::default()
+        };
+        let mut view = JobView {
+            id: "j1".into(),
+            key: Some("web".into()),
+            owner: None,
+            remote_judge: "hdu".into(),
+            request_type: "judge".into(),
+            state: JobState::Running,
+            created_at: 1,
+            updated_at: 1,
+            submission_id: None,
+            handle: None,
+            status: None,
+            problem: None,
+            error: None,
+        };
+        store.insert(&view, &req).unwrap();
+        assert_eq!(store.unfinished().unwrap().len(), 1);
+
+        assert_eq!(store.submissions_since("web", 0).unwrap(), 0);
+
+        view.state = JobState::Done;
+        view.updated_at = 2;
+        view.submission_id = Some("42".into());
+        let event = Envelope {
+            version: 2,
+            seq: 0,
+            request_id: None,
+            message: ServerMessage::Done,
+        };
+        store.append(&view, &event).unwrap();
+
+        let (loaded, events) = store.load("j1").unwrap().unwrap();
+        assert_eq!(loaded.state, JobState::Done);
+        assert_eq!(loaded.key.as_deref(), Some("web"));
+        assert_eq!(events.len(), 1);
+        assert!(store.unfinished().unwrap().is_empty());
+        assert_eq!(store.submissions_since("web", 0).unwrap(), 1);
+        assert_eq!(store.submissions_since("web", 2).unwrap(), 0);
+        assert_eq!(store.submissions_since("app", 0).unwrap(), 0);
+        assert!(store.load("j2").unwrap().is_none());
+    }
+}
@@ -1,16 +1,37 @@
+use super::auth;
+use super::job::Job;
+use super::protocol::ServerMessage;
+use super::queue::Ticket;
+use super::quota::Admission;
+use super::reply::Reply;
+use super::scheduler;
 use super::WsRequest;
 use crate::global::{
-    remote_judge_config, remote_judge_constant::names as remote_judge_names, server_config,
+    remote_judge_config, remote_judge_constant::names as remote_judge_names,
     task_constant::names as task_names,
 };
-use crate::judger::provider::Provider;
+use crate::judger::provider::{Provider, SubmissionHandle};
+use crate::judger::RemoteJudgeConfig;
+use crate::judger::utils::breaker::CircuitOpen;
 use anyhow::anyhow;
-use axum::extract::ws::{Message, WebSocket};
+use tokio::sync::broadcast::error::RecvError;
+
+// gym 与 codeforces 共用配置
+fn judge_config(remote_judge: &str) -> anyhow::Result<&RemoteJudgeConfig> {
+    let name = if remote_judge == remote_judge_names::GYM {
+        remote_judge_names::CODEFORCES
+    } else {
+        remote_judge
+    };
+    remote_judge_config(name).ok_or(anyhow!("不支持 该 OJ 测评"))
+}
 
 async fn judge_task<T: ?Sized + Provider>(
     provider: &T,
-    ws: &mut WebSocket,
+    reply: &Reply,
     req: WsRequest,
+    ticket: Option<Ticket>,
+    admission: &mut Admission,
 ) -> anyhow::Result<()> {
     if req.problem_id.is_none() || req.source.is_none() || req.lang.is_none() {
         return Err(anyhow!("请求参数错误"));
@@ -19,63 +40,126 @@ async fn judge_task<T: ?Sized + Provider>(
     let source = req.source.as_ref().unwrap();
     let lang = req.lang.as_ref().unwrap();
 
-    let Some(rj_config) = remote_judge_config(if req.remote_judge == remote_judge_names::GYM {remote_judge_names::CODEFORCES} else { &req.remote_judge }) else {
-        return Err(anyhow!("不支持 该 OJ 测评"));
-    };
+    let rj_config = judge_config(&req.remote_judge)?;
 
     let Some(lang_id) =  rj_config.lang_map.get(lang) else {
         return Err(anyhow!("不支持该语言"));
     };
 
     let retry_submit_code = || async {
-        // 最大重试 3 次
+        // 最大重试 3 次, 熔断时不再重试
         for _ in 0..2 {
             let submit_code_resp = provider.submit_code(problem_id, source, lang_id).await;
-            if submit_code_resp.is_ok() {
-                return submit_code_resp;
+            match submit_code_resp.as_ref() {
+                Ok(_) => return submit_code_resp,
+                Err(e) if e.is::<CircuitOpen>() => return submit_code_resp,
+                Err(_) => {}
             }
             tokio::time::sleep(std::time::Duration::from_secs(2)).await;
         }
         return provider.submit_code(problem_id, source, lang_id).await;
     };
 
-    let (submission_id, info) = retry_submit_code().await?;
+    // 提交完成后即释放提交名额, 之后的 poll 由 scheduler 负责
+    let handle = retry_submit_code().await;
+    drop(ticket);
+    let handle = handle?;
+    // 提交成功后才计入当日提交次数
+    admission.confirm();
 
-    ws.send(Message::Text(info.to_string())).await?;
+    reply.send(ServerMessage::Accepted {
+        submission_id: handle.remote_id.clone(),
+        account: handle.account.clone(),
+        handle: auth::sign_handle(auth::handle_secret(), &handle),
+        job_id: reply.job_id(),
+    })?;
 
-    let config = server_config();
+    poll_task(reply, &handle).await
+}
 
-    let mut pre_info = "".to_string();
-    let mut sleep_time = config.wait_base;
+// 交给 scheduler 统一 poll, 这里只转发状态变化
+async fn poll_task(reply: &Reply, handle: &SubmissionHandle) -> anyhow::Result<()> {
+    let mut rx = scheduler::watch(handle);
+    while let Some(res) = rx.recv().await {
+        reply.send(ServerMessage::Status(res?))?;
+    }
+    Ok(())
+}
 
-    for _ in 0..config.max_poll_times {
-        match provider.poll(&submission_id).await {
-            Err(_) => {
-                sleep_time = std::cmp::min(sleep_time + config.wait_incr, config.max_wait_time)
+// 从 track 请求中解析出需要继续 poll 的提交
+pub fn track_handle(req: &WsRequest) -> anyhow::Result<SubmissionHandle> {
+    let handle = match req.handle.as_ref() {
+        // handle 由服务端签名, 客户端不能修改其中的账户
+        Some(token) => {
+            let handle = auth::verify_handle(auth::handle_secret(), token)?;
+            if handle.oj != req.remote_judge {
+                return Err(anyhow!("handle 与 remote_judge 不匹配"));
             }
-            Ok(res) => {
-                if res.info != pre_info {
-                    ws.send(Message::Text(serde_json::json!(res).to_string()))
-                        .await?;
-                    if res.is_over {
-                        return Ok(());
-                    }
-                    sleep_time = config.wait_base;
-                    pre_info = res.info;
-                } else {
-                    sleep_time = std::cmp::min(sleep_time + config.wait_incr, config.max_wait_time);
-                }
+            handle
+        }
+        // 必须指明提交使用的账户, 用其他账户 poll 不到结果
+        None => {
+            if req.submission_id.is_none() || req.problem_id.is_none() || req.account.is_none() {
+                return Err(anyhow!("请求参数错误"));
             }
+            SubmissionHandle::from_remote_id(
+                &req.remote_judge,
+                req.account.as_ref().unwrap(),
+                req.problem_id.as_ref().unwrap(),
+                req.submission_id.as_ref().unwrap(),
+            )
         }
-        tokio::time::sleep(tokio::time::Duration::from_secs(sleep_time as u64)).await
+    };
+    if !judge_config(&req.remote_judge)?
+        .accounts
+        .iter()
+        .any(|a| a.handler == handle.account)
+    {
+        return Err(anyhow!("未注册 {} 账户 {}", req.remote_judge, handle.account));
     }
+    Ok(handle)
+}
 
-    Err(anyhow!("超过最大重试次数， 请查看远程测评网站是否不可访问"))
+pub async fn track(reply: &Reply, handle: SubmissionHandle) -> anyhow::Result<()> {
+    poll_task(reply, &handle).await
+}
+
+// 转发任务的消息, 所有订阅者共用任务的 poll 循环
+pub async fn watch(job: &Job, reply: &Reply) -> anyhow::Result<()> {
+    let forward = |message: ServerMessage| -> anyhow::Result<bool> {
+        match message {
+            ServerMessage::Done => Ok(true),
+            ServerMessage::Error { message, .. } => Err(anyhow!(message)),
+            message => reply.send(message).map(|_| false),
+        }
+    };
+
+    let (latest, rx) = job.watch();
+    for message in latest {
+        if forward(message)? {
+            return Ok(());
+        }
+    }
+    let Some(mut rx) = rx else {
+        return Ok(());
+    };
+    loop {
+        match rx.recv().await {
+            Ok(envelope) => {
+                if forward(envelope.message)? {
+                    return Ok(());
+                }
+            }
+            // 跟不上时跳过中间状态, 之后的消息仍会送达
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
 }
 
 async fn get_problem_task<T: ?Sized + Provider>(
     provider: &T,
-    ws: &mut WebSocket,
+    reply: &Reply,
     req: WsRequest,
 ) -> anyhow::Result<()> {
     if req.problem_id.is_none() {
@@ -83,20 +167,19 @@ async fn get_problem_task<T: ?Sized + Provider>(
     }
     let problem_id = req.problem_id.as_ref().unwrap();
     let res = provider.get_problem(problem_id).await?;
-    let _ = ws
-        .send(Message::Text(serde_json::json!(res).to_string()))
-        .await;
-    Ok(())
+    reply.send(ServerMessage::Problem(res))
 }
 
 pub async fn run<T: ?Sized + Provider>(
     provider: &T,
-    ws: &mut WebSocket,
+    reply: &Reply,
     req: WsRequest,
+    ticket: Option<Ticket>,
+    admission: &mut Admission,
 ) -> anyhow::Result<()> {
     match req.request_type.as_str() {
-        task_names::JUDGE => judge_task(provider, ws, req).await,
-        task_names::GET_PROBLEM => get_problem_task(provider, ws, req).await,
+        task_names::JUDGE => judge_task(provider, reply, req, ticket, admission).await,
+        task_names::GET_PROBLEM => get_problem_task(provider, reply, req).await,
         _ => Err(anyhow!("任务类型错误")),
     }
 }
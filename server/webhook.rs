@@ -0,0 +1,275 @@
+// 任务回调: 请求中的 callback_url 以及 api key 配置的 webhooks
+// 每个回调地址一个发送队列, 按消息顺序投递; 网络错误或 5xx 时退避重试, 每次尝试都记录在 JobStore 中
+// 回调地址只能是 http(s), 解析后指向本机或内网的地址会被拒绝 (webhook_allow_hosts 除外), 也不跟随重定向
+use super::auth::mac;
+use super::job::{JobState, JobView};
+use super::store::job_store;
+use super::{Webhook, WsRequest};
+use crate::global::server_config;
+use crate::judger::utils::now_timestamp;
+
+use anyhow::anyhow;
+use hmac::Mac;
+use reqwest::{redirect, Url};
+use serde::{Deserialize, Serialize};
+use simple_log::log::{error, info};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub const SIGNATURE_HEADER: &str = "X-Remote-Judge-Signature";
+pub const EVENT_HEADER: &str = "X-Remote-Judge-Event";
+// 单次回调最多尝试的次数, 间隔从 1 秒开始翻倍
+pub const MAX_ATTEMPTS: u32 = 5;
+
+// 回调的投递记录, GET /jobs/{id}/deliveries 返回
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Delivery {
+    pub job_id: String,
+    pub url: String,
+    pub event: String,
+    pub attempt: u32,
+    pub status: Option<u16>, // http 状态码, 请求未发出时为 None
+    pub error: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: &'a str,
+    job: &'a JobView,
+}
+
+// 任务需要回调的地址
+pub fn targets(req: &WsRequest, key: Option<&str>) -> Vec<Webhook> {
+    let mut hooks: Vec<Webhook> = key
+        .and_then(|name| server_config().api_key(name))
+        .map(|k| k.webhooks.clone())
+        .unwrap_or_default();
+    if let Some(url) = req.callback_url.as_ref() {
+        hooks.push(Webhook {
+            url: url.clone(),
+            updates: req.callback_updates,
+        });
+    }
+    hooks
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || (a == 100 && (b & 0xc0) == 64)) // 100.64.0.0/10
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || (first & 0xfe00) == 0xfc00 // fc00::/7
+                    || (first & 0xffc0) == 0xfe80) // fe80::/10
+            }
+        },
+    }
+}
+
+// 检查回调地址, 返回之后请求时使用的地址; 在 webhook_allow_hosts 中的 host 不检查
+pub async fn resolve(url: &str) -> anyhow::Result<(Url, Option<SocketAddr>)> {
+    let url = Url::parse(url).map_err(|_| anyhow!("回调地址格式错误"))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(anyhow!("回调地址只支持 http 与 https"));
+    }
+    let Some(host) = url.host_str() else {
+        return Err(anyhow!("回调地址格式错误"));
+    };
+    if server_config()
+        .webhook_allow_hosts
+        .iter()
+        .any(|h| h == host)
+    {
+        return Ok((url, None));
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> =
+        tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port))
+            .await?
+            .collect();
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(anyhow!("回调地址不能指向本机或内网"));
+    }
+    let addr = addrs[0];
+    Ok((url, Some(addr)))
+}
+
+// 使用检查时解析得到的地址, 避免发送时再次解析得到内网地址
+fn client(url: &Url, addr: Option<SocketAddr>) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(redirect::Policy::none());
+    if let (Some(host), Some(addr)) = (url.host_str(), addr) {
+        builder = builder.resolve(host, addr);
+    }
+    Ok(builder.build()?)
+}
+
+fn signature(secret: &str, body: &str) -> String {
+    let bytes = mac(secret, body).finalize().into_bytes();
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+// 发送一次回调, 成功返回 http 状态码
+async fn post(client: &reqwest::Client, url: &Url, event: &str, body: &str) -> anyhow::Result<u16> {
+    let mut request = client
+        .post(url.clone())
+        .header("Content-Type", "application/json")
+        .header(EVENT_HEADER, event)
+        .body(body.to_string());
+    if let Some(secret) = server_config().webhook_secret.as_ref() {
+        request = request.header(SIGNATURE_HEADER, signature(secret, body));
+    }
+    Ok(request.send().await?.status().as_u16())
+}
+
+fn log_delivery(
+    job_id: &str,
+    url: &str,
+    event: &str,
+    attempt: u32,
+    status: Option<u16>,
+    error: Option<String>,
+) {
+    let delivery = Delivery {
+        job_id: job_id.into(),
+        url: url.into(),
+        event: event.into(),
+        attempt,
+        status,
+        error,
+        created_at: now_timestamp(),
+    };
+    if let Err(e) = job_store().log_delivery(&delivery) {
+        error!("保存回调记录失败: {}", e);
+    }
+}
+
+async fn deliver(job_id: &str, url: &str, event: &str, body: &str) {
+    // 地址不合法时不重试
+    let prepared = resolve(url)
+        .await
+        .and_then(|(target, addr)| Ok((client(&target, addr)?, target)));
+    let (client, target) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            log_delivery(job_id, url, event, 1, None, Some(e.to_string()));
+            info!("任务 {} 回调 {} 失败: {}", job_id, url, e);
+            return;
+        }
+    };
+    for attempt in 1..=MAX_ATTEMPTS {
+        let (status, error) = match post(&client, &target, event, body).await {
+            Ok(status) => (Some(status), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        log_delivery(job_id, url, event, attempt, status, error);
+        // 只有网络错误与 5xx 值得重试
+        match status {
+            Some(s) if (200..300).contains(&s) => return,
+            Some(s) if s < 500 => break,
+            _ => {}
+        }
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+        }
+    }
+    info!("任务 {} 回调 {} 失败", job_id, url);
+}
+
+// 任务的回调队列, 每个地址一个, 互不阻塞; 发送端随任务一起丢弃后队列在发完剩余消息后结束
+pub struct Notifier {
+    hooks: Vec<(mpsc::UnboundedSender<(&'static str, String)>, bool)>, // (队列, 是否推送中间状态)
+    updates: bool,
+}
+
+impl Notifier {
+    // 没有回调地址时返回 None
+    pub fn new(job_id: &str, hooks: Vec<Webhook>) -> Option<Self> {
+        if hooks.is_empty() {
+            return None;
+        }
+        let updates = hooks.iter().any(|h| h.updates);
+        let hooks = hooks
+            .into_iter()
+            .map(|hook| {
+                let (tx, mut rx) = mpsc::unbounded_channel::<(&'static str, String)>();
+                let job_id = job_id.to_string();
+                tokio::spawn(async move {
+                    while let Some((event, body)) = rx.recv().await {
+                        deliver(&job_id, &hook.url, event, &body).await;
+                    }
+                });
+                (tx, hook.updates)
+            })
+            .collect();
+        Some(Self { hooks, updates })
+    }
+
+    // 任务概况更新后调用, 中间状态只发给需要的地址
+    pub fn notify(&self, view: &JobView, is_status: bool) {
+        let event = match view.state {
+            JobState::Running if is_status && self.updates => "status",
+            JobState::Running => return,
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+        };
+        match serde_json::to_string(&Payload { event, job: view }) {
+            Ok(body) => {
+                for (tx, updates) in self.hooks.iter() {
+                    if *updates || event != "status" {
+                        let _ = tx.send((event, body.clone()));
+                    }
+                }
+            }
+            Err(e) => error!("序列化回调失败: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let sig = signature("secret", r#"{"event":"done"}"#);
+        assert!(sig.starts_with("sha256="));
+        assert_eq!(sig.len(), "sha256=".len() + 64);
+        assert_ne!(sig, signature("other", r#"{"event":"done"}"#));
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "169.254.169.254",
+            "::1",
+            "fd00::1",
+            "::ffff:192.168.1.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(is_public("1.1.1.1".parse().unwrap()));
+        assert!(is_public("2606:4700::1111".parse().unwrap()));
+    }
+}